@@ -0,0 +1,142 @@
+// 文档分块：将长文本切分为带重叠的段落，避免整篇文档被压成一个平均化的向量
+
+/// 单个文本块及其在原文中的字符偏移
+#[derive(Debug, Clone)]
+pub struct Chunk {
+    pub index: usize,
+    pub start_offset: usize,
+    pub end_offset: usize,
+    pub text: String,
+}
+
+/// 默认块大小（约 500 字符，对应约 200 token）
+pub const DEFAULT_CHUNK_CHARS: usize = 500;
+/// 默认重叠比例
+pub const DEFAULT_OVERLAP_RATIO: f32 = 0.15;
+
+/// 使用默认参数对文本分块
+pub fn chunk_text(text: &str) -> Vec<Chunk> {
+    chunk_text_with(text, DEFAULT_CHUNK_CHARS, DEFAULT_OVERLAP_RATIO)
+}
+
+/// 将文本切分为若干个有重叠的块，优先在句子/段落边界处断开
+pub fn chunk_text_with(text: &str, chunk_chars: usize, overlap_ratio: f32) -> Vec<Chunk> {
+    let chars: Vec<char> = text.chars().collect();
+    if chars.is_empty() {
+        return Vec::new();
+    }
+    if chars.len() <= chunk_chars {
+        return vec![Chunk {
+            index: 0,
+            start_offset: 0,
+            end_offset: chars.len(),
+            text: text.to_string(),
+        }];
+    }
+
+    let overlap = ((chunk_chars as f32) * overlap_ratio) as usize;
+    let step = chunk_chars.saturating_sub(overlap).max(1);
+
+    let mut chunks = Vec::new();
+    let mut start = 0usize;
+    let mut index = 0usize;
+    while start < chars.len() {
+        let ideal_end = (start + chunk_chars).min(chars.len());
+        let end = if ideal_end < chars.len() {
+            find_boundary(&chars, start, ideal_end)
+        } else {
+            ideal_end
+        };
+
+        let chunk_str: String = chars[start..end].iter().collect();
+        chunks.push(Chunk {
+            index,
+            start_offset: start,
+            end_offset: end,
+            text: chunk_str,
+        });
+        index += 1;
+
+        if end >= chars.len() {
+            break;
+        }
+        let next_start = start + step;
+        // 边界查找可能把 end 往回拉，确保下一块仍然向前推进
+        start = next_start.min(end);
+        if start <= chunks.last().map(|c| c.start_offset).unwrap_or(0) && !chunks.is_empty() {
+            start = end;
+        }
+    }
+    chunks
+}
+
+/// 按字符偏移在总时长中线性估算某个分块的起止时间（毫秒）
+///
+/// 语音识别服务目前只返回纯文本、没有逐词时间戳，因此退而求其次按字符位置在总时长内
+/// 线性插值——对大多数语速均匀的转写已经够用，能让搜索结果跳转到大致正确的位置。
+pub fn estimate_time_range_ms(total_chars: usize, duration_ms: u64, start_offset: usize, end_offset: usize) -> (u64, u64) {
+    if total_chars == 0 {
+        return (0, 0);
+    }
+    let start_ms = (duration_ms as u128 * start_offset as u128 / total_chars as u128) as u64;
+    let end_ms = (duration_ms as u128 * end_offset as u128 / total_chars as u128) as u64;
+    (start_ms, end_ms)
+}
+
+/// 在 [start, ideal_end] 后半段寻找最近的句子/段落边界（句号、问号、感叹号、换行）
+fn find_boundary(chars: &[char], start: usize, ideal_end: usize) -> usize {
+    let window_start = start + (ideal_end - start) / 2;
+    for i in (window_start..ideal_end).rev() {
+        match chars[i] {
+            '\n' | '。' | '.' | '!' | '?' | '！' | '？' => return i + 1,
+            _ => {}
+        }
+    }
+    ideal_end
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_text_is_a_single_chunk() {
+        let chunks = chunk_text_with("短文本不需要切分", 500, 0.15);
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].start_offset, 0);
+        assert_eq!(chunks[0].end_offset, "短文本不需要切分".chars().count());
+    }
+
+    #[test]
+    fn long_text_splits_with_overlap_and_covers_full_range() {
+        let text = "第一句话。".repeat(40); // 200 字符，每 5 字符一个句号边界
+        let chunks = chunk_text_with(&text, 50, 0.2);
+        assert!(chunks.len() > 1, "超过 chunk_chars 的文本应当被切分为多块");
+
+        // 分块偏移单调不减，且相邻块之间允许重叠但不能有空隙（下一块从上一块结束前开始）
+        for pair in chunks.windows(2) {
+            assert!(pair[1].start_offset < pair[0].end_offset || pair[1].start_offset == pair[0].end_offset);
+            assert!(pair[1].start_offset > pair[0].start_offset, "分块必须向前推进，不能原地踏步");
+        }
+        // 最后一块必须延伸到文本末尾，保证没有内容被漏掉
+        assert_eq!(chunks.last().unwrap().end_offset, text.chars().count());
+    }
+
+    #[test]
+    fn empty_text_has_no_chunks() {
+        assert!(chunk_text_with("", 500, 0.15).is_empty());
+    }
+
+    #[test]
+    fn estimate_time_range_scales_linearly_with_char_offset() {
+        // 总时长 10000ms，文本共 100 字符，第 [25, 50) 段应当落在 [2500ms, 5000ms)
+        let (start_ms, end_ms) = estimate_time_range_ms(100, 10_000, 25, 50);
+        assert_eq!(start_ms, 2_500);
+        assert_eq!(end_ms, 5_000);
+    }
+
+    #[test]
+    fn estimate_time_range_with_zero_total_chars_is_zero() {
+        assert_eq!(estimate_time_range_ms(0, 10_000, 0, 0), (0, 0));
+    }
+}
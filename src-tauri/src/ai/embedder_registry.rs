@@ -0,0 +1,102 @@
+// 具名嵌入器注册表：按分类（category）路由到不同的嵌入器
+//
+// 不同语义域（代码 / 正文 / 视频转写等）用不同模型嵌入效果更好，且避免把 ONNX 模型
+// 与 SimpleEmbedder 回退产生的向量混入同一语义空间里比较。每个嵌入器注册时探测一次
+// 输出维度并缓存，之后 `resolve` 按分类返回 `(嵌入器名称, 嵌入器, 维度)` 供调用方使用。
+
+use crate::ai::knowledge_base::{Embedder, KbError};
+use parking_lot::RwLock;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// 用于探测嵌入器输出维度的占位文本
+const DIMENSION_PROBE_TEXT: &str = "dimension probe";
+
+struct RegisteredEmbedder {
+    embedder: Arc<Embedder>,
+    dimension: usize,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct EmbedderInfo {
+    pub name: String,
+    pub dimension: usize,
+    pub is_semantic: bool,
+}
+
+pub struct EmbedderRegistry {
+    embedders: RwLock<HashMap<String, RegisteredEmbedder>>,
+    /// 分类 -> 嵌入器名称，未显式配置的分类使用 `default_name`
+    category_routes: RwLock<HashMap<String, String>>,
+    default_name: RwLock<String>,
+}
+
+impl EmbedderRegistry {
+    /// 创建注册表并注册一个默认嵌入器，所有未显式路由的分类都使用它
+    pub fn new(default_name: &str, default_embedder: Arc<Embedder>) -> Result<Self, KbError> {
+        let registry = Self {
+            embedders: RwLock::new(HashMap::new()),
+            category_routes: RwLock::new(HashMap::new()),
+            default_name: RwLock::new(default_name.to_string()),
+        };
+        registry.register(default_name, default_embedder)?;
+        Ok(registry)
+    }
+
+    /// 注册（或覆盖同名）一个具名嵌入器
+    pub fn register(&self, name: &str, embedder: Arc<Embedder>) -> Result<(), KbError> {
+        let dimension = embedder.embed(DIMENSION_PROBE_TEXT)?.len();
+        self.embedders.write().insert(name.to_string(), RegisteredEmbedder { embedder, dimension });
+        Ok(())
+    }
+
+    /// 将某个分类路由到指定嵌入器（必须已注册）
+    pub fn route_category(&self, category: &str, embedder_name: &str) -> Result<(), KbError> {
+        if !self.embedders.read().contains_key(embedder_name) {
+            return Err(KbError::EmbedderNotFound(embedder_name.to_string()));
+        }
+        self.category_routes.write().insert(category.to_string(), embedder_name.to_string());
+        Ok(())
+    }
+
+    /// 按分类解析出应使用的嵌入器名称、实例与向量维度
+    pub fn resolve(&self, category: &str) -> Result<(String, Arc<Embedder>, usize), KbError> {
+        let name = self
+            .category_routes
+            .read()
+            .get(category)
+            .cloned()
+            .unwrap_or_else(|| self.default_name.read().clone());
+        let (embedder, dimension) = self.resolve_by_name(&name)?;
+        Ok((name, embedder, dimension))
+    }
+
+    /// 按名称直接取出已注册的嵌入器实例与维度
+    pub fn resolve_by_name(&self, name: &str) -> Result<(Arc<Embedder>, usize), KbError> {
+        let embedders = self.embedders.read();
+        let registered = embedders.get(name).ok_or_else(|| KbError::EmbedderNotFound(name.to_string()))?;
+        Ok((Arc::clone(&registered.embedder), registered.dimension))
+    }
+
+    /// 某个分类当前路由到的嵌入器名称（未显式配置时为默认嵌入器）
+    pub fn category_embedder_name(&self, category: &str) -> String {
+        self.category_routes
+            .read()
+            .get(category)
+            .cloned()
+            .unwrap_or_else(|| self.default_name.read().clone())
+    }
+
+    /// 列出全部已注册的嵌入器
+    pub fn list(&self) -> Vec<EmbedderInfo> {
+        self.embedders
+            .read()
+            .iter()
+            .map(|(name, registered)| EmbedderInfo {
+                name: name.clone(),
+                dimension: registered.dimension,
+                is_semantic: registered.embedder.is_semantic(),
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,235 @@
+// 后台嵌入队列：解耦“解析完成”与“向量化 + 写库”两个阶段
+//
+// `enqueue_document` 只做分块（轻量、同步），真正耗时的 ONNX 推理和 SQLite 写入
+// 交给后台任务按 token 预算攒批处理，并在一次事务中原子提交，避免崩溃导致文档行
+// 和向量不一致。
+
+use crate::ai::chunking::{self, Chunk};
+use crate::ai::embedder_registry::EmbedderRegistry;
+use crate::ai::knowledge_base::{segment_time_range_ms, Document};
+use crate::ai::vector_db::{content_hash, ChunkWrite, DocumentWrite, VectorDb};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::sync::{mpsc, Notify};
+use tokio::time::Duration;
+
+/// 攒批防抖时间：短时间内连续到达的文档会被合并进同一批再一起嵌入
+const DEBOUNCE: Duration = Duration::from_millis(300);
+/// 单批次 token 预算（按约 4 字符 1 token 粗略估算）
+const DEFAULT_TOKEN_BUDGET: usize = 4000;
+/// 单个分块允许的最大字符数，超出部分在入队前截断，避免嵌入模型收到超长输入
+const MAX_CHUNK_CHARS: usize = 2000;
+
+struct QueueItem {
+    document: Document,
+    chunks: Vec<Chunk>,
+}
+
+pub struct EmbeddingQueue {
+    sender: mpsc::UnboundedSender<QueueItem>,
+    in_flight: Arc<AtomicUsize>,
+    idle_notify: Arc<Notify>,
+}
+
+impl EmbeddingQueue {
+    /// 使用默认 token 预算创建队列，并立即启动后台批处理任务
+    pub fn new(
+        vector_db: Arc<VectorDb>, embedders: Arc<EmbedderRegistry>,
+        documents: Arc<parking_lot::RwLock<Vec<Document>>>,
+    ) -> Self {
+        Self::with_token_budget(vector_db, embedders, documents, DEFAULT_TOKEN_BUDGET)
+    }
+
+    pub fn with_token_budget(
+        vector_db: Arc<VectorDb>, embedders: Arc<EmbedderRegistry>,
+        documents: Arc<parking_lot::RwLock<Vec<Document>>>, token_budget: usize,
+    ) -> Self {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let idle_notify = Arc::new(Notify::new());
+
+        tokio::spawn(Self::run(
+            receiver, vector_db, embedders, documents, token_budget,
+            Arc::clone(&in_flight), Arc::clone(&idle_notify),
+        ));
+
+        Self { sender, in_flight, idle_notify }
+    }
+
+    /// 非阻塞地提交一篇已解析完成的文档，分块在此立即完成（轻量），嵌入与入库延后到后台批次
+    pub fn enqueue_document(&self, document: Document) {
+        let chunks = chunking::chunk_text(&document.content)
+            .into_iter()
+            .map(truncate_chunk)
+            .collect();
+
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        if self.sender.send(QueueItem { document, chunks }).is_err() {
+            // 后台任务已退出（例如进程关闭过程中），回滚计数避免 flush 永久挂起
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+
+    /// 等待当前已提交的全部任务完成向量化与入库
+    ///
+    /// `notify_waiters` 不会像 `notify_one` 那样为错过的通知留一个许可：如果在
+    /// `load` 和 `notified().await` 之间 `run` 恰好跑完一批并发出通知，这次通知
+    /// 就会丢失，`flush` 可能永久挂起。这里先用 `enable()` 把等待者提前注册好，
+    /// 再检查计数器，确保检查之后发生的任何通知都不会被错过。
+    pub async fn flush(&self) {
+        loop {
+            let notified = self.idle_notify.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+
+            if self.in_flight.load(Ordering::SeqCst) == 0 {
+                return;
+            }
+
+            notified.await;
+        }
+    }
+
+    async fn run(
+        mut receiver: mpsc::UnboundedReceiver<QueueItem>,
+        vector_db: Arc<VectorDb>, embedders: Arc<EmbedderRegistry>,
+        documents: Arc<parking_lot::RwLock<Vec<Document>>>,
+        token_budget: usize, in_flight: Arc<AtomicUsize>, idle_notify: Arc<Notify>,
+    ) {
+        let mut batch: Vec<QueueItem> = Vec::new();
+
+        loop {
+            let next = if batch.is_empty() {
+                receiver.recv().await
+            } else {
+                match tokio::time::timeout(DEBOUNCE, receiver.recv()).await {
+                    Ok(next) => next,
+                    Err(_) => None, // 防抖超时，冲刷已攒的批次
+                }
+            };
+
+            let debounce_timed_out = next.is_none() && !batch.is_empty();
+            let channel_closed = next.is_none() && batch.is_empty() && receiver.is_closed();
+
+            if let Some(item) = next {
+                batch.push(item);
+                let tokens_used: usize = batch
+                    .iter()
+                    .flat_map(|item| item.chunks.iter())
+                    .map(|chunk| approx_tokens(&chunk.text))
+                    .sum();
+                if tokens_used < token_budget {
+                    continue; // 预算未满，继续攒批等待防抖或后续文档
+                }
+            } else if channel_closed {
+                return;
+            } else if !debounce_timed_out {
+                continue;
+            }
+
+            let flushed = std::mem::take(&mut batch);
+            let count = flushed.len();
+            Self::flush_batch(&vector_db, &embedders, &documents, flushed).await;
+            in_flight.fetch_sub(count, Ordering::SeqCst);
+            idle_notify.notify_waiters();
+        }
+    }
+
+    /// 批量嵌入（命中嵌入缓存的分块跳过推理，按各文档分类路由到对应嵌入器）并在一次 SQLite 事务中
+    /// 原子提交，成功后才更新内存中的文档列表
+    async fn flush_batch(
+        vector_db: &Arc<VectorDb>, embedders: &Arc<EmbedderRegistry>,
+        documents: &Arc<parking_lot::RwLock<Vec<Document>>>, items: Vec<QueueItem>,
+    ) {
+        let mut prepared: Vec<(Document, String, Vec<(usize, String, usize, usize, Option<i64>, Option<i64>, Vec<f32>)>)> =
+            Vec::with_capacity(items.len());
+
+        for item in items {
+            let (embedder_name, embedder, _dimension) = match embedders.resolve(&item.document.category) {
+                Ok(resolved) => resolved,
+                Err(e) => {
+                    tracing::warn!("文档分类 {} 找不到对应嵌入器，跳过该文档: {}", item.document.category, e);
+                    continue;
+                }
+            };
+
+            let total_chars = item.document.content.chars().count();
+            let mut chunk_writes = Vec::with_capacity(item.chunks.len());
+            for chunk in &item.chunks {
+                let hash = content_hash(&chunk.text);
+                let cached = vector_db.cache_get(&hash, &embedder_name).ok().flatten();
+                let embedding = match cached {
+                    Some(embedding) => Some(embedding),
+                    None => match embedder.embed(&chunk.text) {
+                        Ok(embedding) => {
+                            let _ = vector_db.cache_put(&hash, &embedder_name, &embedding);
+                            Some(embedding)
+                        }
+                        Err(e) => {
+                            tracing::warn!("后台向量化分块失败，跳过该分块: {}", e);
+                            None
+                        }
+                    },
+                };
+                if let Some(embedding) = embedding {
+                    let (start_ms, end_ms) = segment_time_range_ms(item.document.duration_ms, total_chars, chunk);
+                    chunk_writes.push((
+                        chunk.index, chunk.text.clone(), chunk.start_offset, chunk.end_offset, start_ms, end_ms, embedding,
+                    ));
+                }
+            }
+            prepared.push((item.document, embedder_name, chunk_writes));
+        }
+
+        let document_writes: Vec<DocumentWrite> = prepared
+            .iter()
+            .map(|(doc, embedder_name, chunk_writes)| DocumentWrite {
+                id: &doc.id,
+                name: &doc.name,
+                category: &doc.category,
+                content: &doc.content,
+                source_path: doc.source_path.as_deref(),
+                backup_path: doc.backup_path.as_deref(),
+                file_type: &doc.file_type,
+                created_at: &doc.created_at,
+                embedder_name,
+                duration_ms: doc.duration_ms,
+                chunks: chunk_writes
+                    .iter()
+                    .map(|(index, text, start, end, start_ms, end_ms, embedding)| ChunkWrite {
+                        index: *index,
+                        text,
+                        start_offset: *start,
+                        end_offset: *end,
+                        start_ms: *start_ms,
+                        end_ms: *end_ms,
+                        embedding,
+                    })
+                    .collect(),
+            })
+            .collect();
+
+        if let Err(e) = vector_db.commit_documents(&document_writes) {
+            tracing::error!("批量提交向量数据失败，本批 {} 篇文档未写入: {}", prepared.len(), e);
+            return;
+        }
+
+        let mut docs = documents.write();
+        for (doc, _, _) in prepared {
+            docs.push(doc);
+        }
+    }
+}
+
+fn truncate_chunk(mut chunk: Chunk) -> Chunk {
+    if chunk.text.chars().count() > MAX_CHUNK_CHARS {
+        let truncated: String = chunk.text.chars().take(MAX_CHUNK_CHARS).collect();
+        chunk.end_offset = chunk.start_offset + truncated.chars().count();
+        chunk.text = truncated;
+    }
+    chunk
+}
+
+fn approx_tokens(text: &str) -> usize {
+    (text.chars().count() / 4).max(1)
+}
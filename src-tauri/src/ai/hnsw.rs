@@ -0,0 +1,406 @@
+// HNSW（Hierarchical Navigable Small World）近似最近邻索引
+//
+// 用于替代全量扫描的暴力余弦相似度搜索：插入时为每个节点随机分配一个最高层级，
+// 自上而下贪心连接到最近的 M 个邻居；查询时从入口点逐层下降，最底层用大小为
+// ef 的候选集合做 best-first 搜索。索引本身只持有 key + 向量，不关心业务语义，
+// 持久化快照的读写由 `VectorDb` 负责。
+
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+
+#[derive(Debug, Clone)]
+pub struct HnswConfig {
+    /// 每层每个节点保留的最大邻居数（第 0 层允许 2*m）
+    pub m: usize,
+    /// 构建时的候选集合大小，越大召回越高但构建越慢
+    pub ef_construction: usize,
+    /// 查询时默认的候选集合大小
+    pub ef_search: usize,
+}
+
+impl Default for HnswConfig {
+    fn default() -> Self {
+        Self { m: 16, ef_construction: 200, ef_search: 64 }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct HnswNode {
+    pub key: String,
+    pub embedding: Vec<f32>,
+    pub level: usize,
+    /// `neighbors[layer]` 为该节点在对应层的邻居节点 id 列表
+    pub neighbors: Vec<Vec<u32>>,
+}
+
+#[derive(Clone, Copy)]
+struct ScoredId {
+    id: u32,
+    score: f32,
+}
+
+impl PartialEq for ScoredId {
+    fn eq(&self, other: &Self) -> bool {
+        self.score == other.score
+    }
+}
+impl Eq for ScoredId {}
+impl PartialOrd for ScoredId {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.score.partial_cmp(&other.score)
+    }
+}
+impl Ord for ScoredId {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.partial_cmp(other).unwrap_or(Ordering::Equal)
+    }
+}
+
+#[derive(Clone)]
+pub struct HnswIndex {
+    config: HnswConfig,
+    /// 层级分布参数 mL，用于几何分布采样每个新节点的最高层
+    m_l: f32,
+    nodes: HashMap<u32, HnswNode>,
+    key_to_id: HashMap<String, u32>,
+    entry_point: Option<u32>,
+    next_id: u32,
+}
+
+impl HnswIndex {
+    pub fn new(config: HnswConfig) -> Self {
+        let m_l = 1.0 / (config.m.max(2) as f32).ln();
+        Self {
+            config,
+            m_l,
+            nodes: HashMap::new(),
+            key_to_id: HashMap::new(),
+            entry_point: None,
+            next_id: 0,
+        }
+    }
+
+    /// 从持久化快照恢复索引
+    pub fn restore(config: HnswConfig, nodes: Vec<(u32, HnswNode)>, next_id: u32, entry_point: Option<u32>) -> Self {
+        let m_l = 1.0 / (config.m.max(2) as f32).ln();
+        let mut key_to_id = HashMap::new();
+        let mut map = HashMap::new();
+        for (id, node) in nodes {
+            key_to_id.insert(node.key.clone(), id);
+            map.insert(id, node);
+        }
+        Self { config, m_l, nodes: map, key_to_id, entry_point, next_id }
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn entry_point(&self) -> Option<u32> {
+        self.entry_point
+    }
+
+    pub fn next_id(&self) -> u32 {
+        self.next_id
+    }
+
+    pub fn nodes_snapshot(&self) -> Vec<(u32, HnswNode)> {
+        self.nodes.iter().map(|(id, node)| (*id, node.clone())).collect()
+    }
+
+    fn random_level(&self) -> usize {
+        use rand::Rng;
+        let mut rng = rand::thread_rng();
+        let r: f32 = rng.gen::<f32>().max(1e-9);
+        (-r.ln() * self.m_l).floor() as usize
+    }
+
+    /// 余弦相似度。维度不一致时返回 `None` 而非静默当作 0 分——图里同时混入不同维度的
+    /// 节点通常意味着调用方没有按 `embedder_name` 做好隔离，不应该让这种节点参与排序。
+    fn cosine(a: &[f32], b: &[f32]) -> Option<f32> {
+        if a.len() != b.len() {
+            return None;
+        }
+        let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+        let norm_a: f32 = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+        let norm_b: f32 = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+        if norm_a == 0.0 || norm_b == 0.0 {
+            return Some(0.0);
+        }
+        Some(dot / (norm_a * norm_b))
+    }
+
+    /// 在指定层上做贪心 best-first 搜索，维护一个大小为 `ef` 的动态候选集合
+    fn search_layer(&self, query: &[f32], entry_points: &[u32], ef: usize, layer: usize) -> Vec<ScoredId> {
+        let mut visited: HashSet<u32> = entry_points.iter().copied().collect();
+        let mut to_explore: Vec<ScoredId> = Vec::new();
+        let mut found: Vec<ScoredId> = Vec::new();
+
+        for &ep in entry_points {
+            if let Some(node) = self.nodes.get(&ep) {
+                if let Some(score) = Self::cosine(query, &node.embedding) {
+                    to_explore.push(ScoredId { id: ep, score });
+                    found.push(ScoredId { id: ep, score });
+                }
+            }
+        }
+
+        while let Some(pos) = to_explore
+            .iter()
+            .enumerate()
+            .max_by(|a, b| a.1.score.partial_cmp(&b.1.score).unwrap_or(Ordering::Equal))
+            .map(|(i, _)| i)
+        {
+            let current = to_explore.swap_remove(pos);
+
+            let worst_found = found.iter().map(|s| s.score).fold(f32::INFINITY, f32::min);
+            if found.len() >= ef.max(1) && current.score < worst_found {
+                break;
+            }
+
+            let neighbors = self
+                .nodes
+                .get(&current.id)
+                .and_then(|n| n.neighbors.get(layer))
+                .cloned()
+                .unwrap_or_default();
+
+            for neighbor_id in neighbors {
+                if !visited.insert(neighbor_id) {
+                    continue;
+                }
+                if let Some(neighbor) = self.nodes.get(&neighbor_id) {
+                    if let Some(score) = Self::cosine(query, &neighbor.embedding) {
+                        to_explore.push(ScoredId { id: neighbor_id, score });
+                        found.push(ScoredId { id: neighbor_id, score });
+                        found.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(Ordering::Equal));
+                        found.truncate(ef.max(1));
+                    }
+                }
+            }
+        }
+
+        found.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(Ordering::Equal));
+        found
+    }
+
+    /// 将 `node_id` 在 `layer` 层的邻居裁剪到最多 `max_conn` 个，保留与其最相似的
+    fn prune_neighbors(&mut self, node_id: u32, layer: usize, max_conn: usize) {
+        let (embedding, current) = match self.nodes.get(&node_id) {
+            Some(node) => (node.embedding.clone(), node.neighbors.get(layer).cloned().unwrap_or_default()),
+            None => return,
+        };
+        if current.len() <= max_conn {
+            return;
+        }
+
+        let mut scored: Vec<ScoredId> = current
+            .iter()
+            .filter_map(|&nid| {
+                let n = self.nodes.get(&nid)?;
+                let score = Self::cosine(&embedding, &n.embedding)?;
+                Some(ScoredId { id: nid, score })
+            })
+            .collect();
+        scored.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(Ordering::Equal));
+        scored.truncate(max_conn);
+        let pruned: Vec<u32> = scored.into_iter().map(|s| s.id).collect();
+
+        if let Some(node) = self.nodes.get_mut(&node_id) {
+            if node.neighbors.len() <= layer {
+                node.neighbors.resize(layer + 1, Vec::new());
+            }
+            node.neighbors[layer] = pruned;
+        }
+    }
+
+    /// 插入（或覆盖同名 key 的）一个向量，返回分配的内部 node_id 与层级
+    pub fn insert(&mut self, key: String, embedding: Vec<f32>) -> (u32, usize) {
+        if let Some(&old_id) = self.key_to_id.get(&key) {
+            self.remove_by_id(old_id);
+        }
+
+        let level = self.random_level();
+        let id = self.next_id;
+        self.next_id += 1;
+        let mut node = HnswNode { key: key.clone(), embedding: embedding.clone(), level, neighbors: vec![Vec::new(); level + 1] };
+
+        let Some(entry_id) = self.entry_point else {
+            self.nodes.insert(id, node);
+            self.key_to_id.insert(key, id);
+            self.entry_point = Some(id);
+            return (id, level);
+        };
+
+        let entry_level = self.nodes.get(&entry_id).map(|n| n.level).unwrap_or(0);
+        let mut current = vec![entry_id];
+
+        // 从入口点的最高层贪心下降到新节点的最高层
+        for layer in (level + 1..=entry_level).rev() {
+            let found = self.search_layer(&embedding, &current, 1, layer);
+            if let Some(best) = found.first() {
+                current = vec![best.id];
+            }
+        }
+
+        // 从 min(新节点层级, 入口点层级) 开始向下，每层建立双向连接
+        for layer in (0..=level.min(entry_level)).rev() {
+            let found = self.search_layer(&embedding, &current, self.config.ef_construction, layer);
+            let max_conn = if layer == 0 { self.config.m * 2 } else { self.config.m };
+            let selected: Vec<u32> = found.iter().take(max_conn).map(|s| s.id).collect();
+
+            node.neighbors[layer] = selected.clone();
+            for &neighbor_id in &selected {
+                if let Some(neighbor) = self.nodes.get_mut(&neighbor_id) {
+                    if neighbor.neighbors.len() <= layer {
+                        neighbor.neighbors.resize(layer + 1, Vec::new());
+                    }
+                    neighbor.neighbors[layer].push(id);
+                }
+                self.prune_neighbors(neighbor_id, layer, max_conn);
+            }
+
+            if !selected.is_empty() {
+                current = selected;
+            }
+        }
+
+        self.nodes.insert(id, node);
+        self.key_to_id.insert(key, id);
+        if level > entry_level {
+            self.entry_point = Some(id);
+        }
+        (id, level)
+    }
+
+    fn remove_by_id(&mut self, id: u32) {
+        let Some(node) = self.nodes.remove(&id) else { return };
+        self.key_to_id.remove(&node.key);
+
+        for layer_neighbors in &node.neighbors {
+            for &neighbor_id in layer_neighbors {
+                if let Some(neighbor) = self.nodes.get_mut(&neighbor_id) {
+                    for layer_edges in &mut neighbor.neighbors {
+                        layer_edges.retain(|&nid| nid != id);
+                    }
+                }
+            }
+        }
+
+        if self.entry_point == Some(id) {
+            self.entry_point = self.nodes.iter().max_by_key(|(_, n)| n.level).map(|(id, _)| *id);
+        }
+    }
+
+    /// 删除某个 key 对应的节点
+    pub fn remove(&mut self, key: &str) {
+        if let Some(&id) = self.key_to_id.get(key) {
+            self.remove_by_id(id);
+        }
+    }
+
+    /// 近似最近邻查询：从入口点逐层下降，最底层用 best-first 搜索返回 top `limit`
+    pub fn search(&self, query: &[f32], limit: usize) -> Vec<(String, f32)> {
+        let ef = self.config.ef_search.max(limit);
+        let Some(entry_id) = self.entry_point else { return Vec::new() };
+        let entry_level = match self.nodes.get(&entry_id) {
+            Some(node) => node.level,
+            None => return Vec::new(),
+        };
+
+        let mut current = vec![entry_id];
+        for layer in (1..=entry_level).rev() {
+            let found = self.search_layer(query, &current, 1, layer);
+            if let Some(best) = found.first() {
+                current = vec![best.id];
+            }
+        }
+
+        let found = self.search_layer(query, &current, ef, 0);
+        found
+            .into_iter()
+            .take(limit)
+            .filter_map(|s| self.nodes.get(&s.id).map(|n| (n.key.clone(), s.score)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 确定性的伪随机向量生成器（线性同余），避免测试结果依赖真实随机源
+    fn pseudo_random_vector(seed: u64, dim: usize) -> Vec<f32> {
+        let mut state = seed.wrapping_mul(2_654_435_761).wrapping_add(1);
+        (0..dim)
+            .map(|_| {
+                state = state.wrapping_mul(6_364_136_223_846_793_005).wrapping_add(1_442_695_040_888_963_407);
+                ((state >> 33) as f32 / u32::MAX as f32) * 2.0 - 1.0
+            })
+            .collect()
+    }
+
+    fn bruteforce_top_k(vectors: &[(String, Vec<f32>)], query: &[f32], k: usize) -> Vec<String> {
+        let mut scored: Vec<(String, f32)> = vectors
+            .iter()
+            .filter_map(|(key, v)| HnswIndex::cosine(query, v).map(|score| (key.clone(), score)))
+            .collect();
+        scored.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(Ordering::Equal));
+        scored.into_iter().take(k).map(|(key, _)| key).collect()
+    }
+
+    #[test]
+    fn ann_search_roughly_matches_bruteforce_ranking() {
+        const DIM: usize = 16;
+        const N: usize = 300;
+        const K: usize = 10;
+
+        let mut index = HnswIndex::new(HnswConfig::default());
+        let mut vectors = Vec::with_capacity(N);
+        for i in 0..N {
+            let key = format!("doc-{i}");
+            let embedding = pseudo_random_vector(i as u64, DIM);
+            index.insert(key.clone(), embedding.clone());
+            vectors.push((key, embedding));
+        }
+
+        let query = pseudo_random_vector(9_999, DIM);
+        let ann_top_k: HashSet<String> = index.search(&query, K).into_iter().map(|(key, _)| key).collect();
+        let bruteforce_top_k: HashSet<String> = bruteforce_top_k(&vectors, &query, K).into_iter().collect();
+
+        let overlap = ann_top_k.intersection(&bruteforce_top_k).count();
+        assert!(
+            overlap >= K * 7 / 10,
+            "ANN top-{K} 应当与暴力余弦排序的 top-{K} 大部分重合，实际只重合 {overlap} 个"
+        );
+    }
+
+    #[test]
+    fn dimension_mismatched_node_is_filtered_out_of_ann_results() {
+        const DIM: usize = 8;
+        let mut index = HnswIndex::new(HnswConfig::default());
+        for i in 0..60 {
+            index.insert(format!("doc-{i}"), pseudo_random_vector(i as u64, DIM));
+        }
+        // 混入一个维度不同的节点——模拟不同嵌入器的向量混入同一张图
+        index.insert("mismatched".to_string(), pseudo_random_vector(123, DIM + 4));
+
+        let query = pseudo_random_vector(7, DIM);
+        let results = index.search(&query, index.len());
+        assert!(
+            results.iter().all(|(key, _)| key != "mismatched"),
+            "维度不匹配的节点不应该被计入搜索结果（不能静默记 0 分参与排序）"
+        );
+    }
+
+    #[test]
+    fn cosine_returns_none_on_dimension_mismatch_and_some_otherwise() {
+        assert_eq!(HnswIndex::cosine(&[1.0, 0.0], &[1.0, 0.0, 0.0]), None);
+        assert_eq!(HnswIndex::cosine(&[1.0, 0.0], &[0.0, 1.0]), Some(0.0));
+        assert_eq!(HnswIndex::cosine(&[1.0, 0.0], &[1.0, 0.0]), Some(1.0));
+    }
+}
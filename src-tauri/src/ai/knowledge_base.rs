@@ -1,7 +1,10 @@
 // 知识库管理
 
+use crate::ai::chunking;
+use crate::ai::embedder_registry::{EmbedderInfo, EmbedderRegistry};
+use crate::ai::embedding_queue::EmbeddingQueue;
 use crate::ai::onnx_embedder::{EmbedderError, OnnxEmbedder};
-use crate::ai::vector_db::{VectorDb, VectorDbError};
+use crate::ai::vector_db::{ChunkWrite, DocumentWrite, VectorDb, VectorDbError};
 use chrono::Utc;
 use parking_lot::Mutex;
 use std::path::{Path, PathBuf};
@@ -21,6 +24,8 @@ pub enum KbError {
     EmbeddingFailed(String),
     #[error("嵌入模型未安装")]
     EmbedderNotInstalled,
+    #[error("未找到名为 {0} 的嵌入器")]
+    EmbedderNotFound(String),
     #[error("IO 错误: {0}")]
     IoError(#[from] std::io::Error),
     #[error("向量数据库错误: {0}")]
@@ -41,6 +46,8 @@ pub struct Document {
     /// 文件类型 (txt, md, docx, pdf, mp4, etc.)
     pub file_type: String,
     pub created_at: String,
+    /// 源媒体总时长（毫秒），如视频转写文档；非时间轴文档为 `None`
+    pub duration_ms: Option<i64>,
 }
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
@@ -48,6 +55,9 @@ pub struct SearchResult {
     pub document: Document,
     pub relevance: f32,
     pub snippet: String,
+    /// 命中分块在源媒体中的起始时间（毫秒），用于搜索结果跳转回视频对应位置；
+    /// 非时间轴文档或旧文档（无分块记录）为 `None`
+    pub segment_start_ms: Option<i64>,
 }
 
 /// 嵌入器类型（支持回退）
@@ -75,20 +85,66 @@ impl Embedder {
 
 pub struct KnowledgeBase {
     vector_db: Arc<VectorDb>,
-    embedder: Arc<Embedder>,
+    /// 具名嵌入器注册表：按文档分类路由到不同的嵌入器，而不是全库共用一个
+    embedders: Arc<EmbedderRegistry>,
     documents: Arc<parking_lot::RwLock<Vec<Document>>>,
+    embedding_queue: Arc<EmbeddingQueue>,
 }
 
 impl Clone for KnowledgeBase {
     fn clone(&self) -> Self {
         Self {
             vector_db: Arc::clone(&self.vector_db),
-            embedder: Arc::clone(&self.embedder),
+            embedders: Arc::clone(&self.embedders),
             documents: Arc::clone(&self.documents),
+            embedding_queue: Arc::clone(&self.embedding_queue),
         }
     }
 }
 
+/// 若文档带有总时长（如视频转写），按字符偏移线性估算某个分块的起止时间戳；否则返回 `(None, None)`
+pub(crate) fn segment_time_range_ms(duration_ms: Option<i64>, total_chars: usize, chunk: &chunking::Chunk) -> (Option<i64>, Option<i64>) {
+    match duration_ms {
+        Some(duration_ms) if duration_ms >= 0 => {
+            let (start_ms, end_ms) = chunking::estimate_time_range_ms(
+                total_chars, duration_ms as u64, chunk.start_offset, chunk.end_offset,
+            );
+            (Some(start_ms as i64), Some(end_ms as i64))
+        }
+        _ => (None, None),
+    }
+}
+
+/// 转写文本备份文件的内容：有总时长时写成带分块时间戳的 WebVTT 字幕，把各分块估算的时间范围
+/// 保存在 `.txt` 备份里；没有时长信息则原样保存纯文本。重新导入该 `.txt` 时按普通文本解析，
+/// 不会从字幕中读回时间戳
+fn transcript_backup_content(content: &str, duration_ms: Option<u64>) -> String {
+    let Some(duration_ms) = duration_ms else {
+        return content.to_string();
+    };
+    let total_chars = content.chars().count();
+    let chunks = chunking::chunk_text(content);
+
+    let mut vtt = String::from("WEBVTT\n\n");
+    for chunk in &chunks {
+        let (start_ms, end_ms) = chunking::estimate_time_range_ms(total_chars, duration_ms, chunk.start_offset, chunk.end_offset);
+        vtt.push_str(&format!(
+            "{} --> {}\n{}\n\n",
+            format_vtt_timestamp(start_ms), format_vtt_timestamp(end_ms), chunk.text,
+        ));
+    }
+    vtt
+}
+
+/// 毫秒转为 WebVTT 要求的 `HH:MM:SS.mmm` 时间戳格式
+fn format_vtt_timestamp(ms: u64) -> String {
+    let hours = ms / 3_600_000;
+    let minutes = (ms % 3_600_000) / 60_000;
+    let seconds = (ms % 60_000) / 1000;
+    let millis = ms % 1000;
+    format!("{:02}:{:02}:{:02}.{:03}", hours, minutes, seconds, millis)
+}
+
 impl KnowledgeBase {
     /// 创建新的知识库实例
     pub fn new(db_path: &Path) -> Result<Self, KbError> {
@@ -101,26 +157,29 @@ impl KnowledgeBase {
         let documents = Arc::new(parking_lot::RwLock::new(Vec::new()));
 
         // 尝试加载 ONNX 嵌入器，失败则回退到 SimpleEmbedder
-        let embedder = if let Some(dir) = model_dir {
+        // 嵌入器名称标识当前生效的嵌入器（模型目录或 "simple"），用于嵌入缓存的 key 以及向量行的归属，
+        // 这样切换嵌入器后旧向量不会被误当作有效缓存复用，也不会与新向量混入同一语义空间比较
+        let (default_embedder, default_name) = if let Some(dir) = model_dir {
             match OnnxEmbedder::new(dir) {
                 Ok(onnx) => {
                     tracing::info!("使用 ONNX 语义嵌入模型: {:?}", dir);
-                    Arc::new(Embedder::Onnx(Mutex::new(onnx)))
+                    (Arc::new(Embedder::Onnx(Mutex::new(onnx))), format!("onnx:{}", dir.display()))
                 }
                 Err(e) => {
                     tracing::warn!("ONNX 模型加载失败，回退到 SimpleEmbedder: {}", e);
-                    Arc::new(Embedder::Simple(SimpleEmbedder::new(384)))
+                    (Arc::new(Embedder::Simple(SimpleEmbedder::new(384))), "simple".to_string())
                 }
             }
         } else {
             tracing::info!("未指定模型目录，使用 SimpleEmbedder");
-            Arc::new(Embedder::Simple(SimpleEmbedder::new(384)))
+            (Arc::new(Embedder::Simple(SimpleEmbedder::new(384))), "simple".to_string())
         };
+        let embedders = Arc::new(EmbedderRegistry::new(&default_name, default_embedder)?);
 
         // 从数据库加载已保存的文档
         if let Ok(saved_docs) = vector_db.load_documents() {
             let mut docs = documents.write();
-            for (id, name, category, content, source_path, backup_path, file_type, created_at) in saved_docs {
+            for (id, name, category, content, source_path, backup_path, file_type, created_at, duration_ms) in saved_docs {
                 docs.push(Document {
                     id,
                     name,
@@ -130,27 +189,182 @@ impl KnowledgeBase {
                     backup_path,
                     file_type,
                     created_at,
+                    duration_ms,
                 });
             }
             tracing::info!("从数据库加载了 {} 个文档", docs.len());
         }
 
+        let embedding_queue = Arc::new(EmbeddingQueue::new(
+            Arc::clone(&vector_db), Arc::clone(&embedders), Arc::clone(&documents),
+        ));
+
         Ok(Self {
             vector_db,
-            embedder,
+            embedders,
             documents,
+            embedding_queue,
         })
     }
 
+    /// 注册（或覆盖同名）一个具名嵌入器，供后续 `route_category`/重新入库使用
+    pub fn register_embedder(&self, name: &str, model_dir: Option<&Path>) -> Result<(), KbError> {
+        let embedder = match model_dir {
+            Some(dir) => Arc::new(Embedder::Onnx(Mutex::new(OnnxEmbedder::new(dir)?))),
+            None => Arc::new(Embedder::Simple(SimpleEmbedder::new(384))),
+        };
+        self.embedders.register(name, embedder)
+    }
+
+    /// 将某个文档分类路由到指定（必须已注册）的嵌入器
+    pub fn route_category(&self, category: &str, embedder_name: &str) -> Result<(), KbError> {
+        self.embedders.route_category(category, embedder_name)
+    }
+
+    /// 列出全部已注册的嵌入器
+    pub fn list_embedders(&self) -> Vec<EmbedderInfo> {
+        self.embedders.list()
+    }
+
+    /// 重新路由后，对某个分类下的全部文档用其当前嵌入器重新分块、向量化并入库，
+    /// 替换掉该分类下此前可能由另一个嵌入器产生的向量
+    ///
+    /// 通过 `commit_documents` 一次性提交整批文档：复用它内建的“按旧 embedder_name 清理旧分块”
+    /// 的逻辑，把这些文档在旧嵌入器 HNSW 图里的节点一并移除，且每个受影响的 HNSW 索引只随该事务落盘一次。
+    pub async fn reindex_category(&self, category: &str) -> Result<usize, KbError> {
+        let docs: Vec<Document> = self.documents.read().iter().filter(|d| d.category == category).cloned().collect();
+        let (embedder_name, embedder, _dimension) = self.embedders.resolve(category)?;
+
+        let mut prepared: Vec<(&Document, Vec<(usize, String, usize, usize, Option<i64>, Option<i64>, Vec<f32>)>)> =
+            Vec::with_capacity(docs.len());
+        for doc in &docs {
+            let total_chars = doc.content.chars().count();
+            let chunks = chunking::chunk_text(&doc.content);
+            let mut chunk_writes = Vec::with_capacity(chunks.len());
+            for chunk in &chunks {
+                let embedding = self.embed_with_cache(&chunk.text, &embedder_name, &embedder)?;
+                let (start_ms, end_ms) = segment_time_range_ms(doc.duration_ms, total_chars, chunk);
+                chunk_writes.push((
+                    chunk.index, chunk.text.clone(), chunk.start_offset, chunk.end_offset, start_ms, end_ms, embedding,
+                ));
+            }
+            prepared.push((doc, chunk_writes));
+        }
+
+        let document_writes: Vec<DocumentWrite> = prepared
+            .iter()
+            .map(|(doc, chunk_writes)| DocumentWrite {
+                id: &doc.id,
+                name: &doc.name,
+                category: &doc.category,
+                content: &doc.content,
+                source_path: doc.source_path.as_deref(),
+                backup_path: doc.backup_path.as_deref(),
+                file_type: &doc.file_type,
+                created_at: &doc.created_at,
+                embedder_name: &embedder_name,
+                duration_ms: doc.duration_ms,
+                chunks: chunk_writes
+                    .iter()
+                    .map(|(index, text, start, end, start_ms, end_ms, embedding)| ChunkWrite {
+                        index: *index,
+                        text,
+                        start_offset: *start,
+                        end_offset: *end,
+                        start_ms: *start_ms,
+                        end_ms: *end_ms,
+                        embedding,
+                    })
+                    .collect(),
+            })
+            .collect();
+
+        self.vector_db.commit_documents(&document_writes)?;
+        Ok(docs.len())
+    }
+
     /// 添加文档到知识库
-    /// 
+    ///
     /// - `path`: 源文件路径（可选）
     /// - `content`: 直接提供的文本内容（可选，如视频转写文本）
     /// - `category`: 分类（documents, video-transcript 等）
     /// - `backup_dir`: 知识库备份目录（可选），用于备份原始文件
+    /// - `duration_ms`: 源媒体总时长（毫秒），如视频转写时可提供，用于为分块估算时间戳
     pub async fn add_document(
         &self, path: Option<&PathBuf>, content: Option<String>,
-        category: &str, backup_dir: Option<&PathBuf>,
+        category: &str, backup_dir: Option<&PathBuf>, duration_ms: Option<u64>,
+    ) -> Result<Document, KbError> {
+        let doc = self.prepare_document(path, content, category, backup_dir, duration_ms)?;
+        let total_chars = doc.content.chars().count();
+
+        // 按分类路由到对应的嵌入器，再按段落切分文档逐块生成向量嵌入（命中嵌入缓存时跳过重复向量化），
+        // 避免长文档被压成一个平均化的向量
+        let (embedder_name, embedder, _dimension) = self.embedders.resolve(category)?;
+        let chunks = chunking::chunk_text(&doc.content);
+        let mut chunk_writes = Vec::with_capacity(chunks.len());
+        for chunk in &chunks {
+            let embedding = self.embed_with_cache(&chunk.text, &embedder_name, &embedder)?;
+            let (start_ms, end_ms) = segment_time_range_ms(doc.duration_ms, total_chars, chunk);
+            chunk_writes.push((
+                chunk.index, chunk.text.clone(), chunk.start_offset, chunk.end_offset, start_ms, end_ms, embedding,
+            ));
+        }
+
+        // 元数据与全部分块向量在一次事务中原子提交，
+        // HNSW 索引也只随该事务全量持久化一次，而不是每个分块各触发一次
+        let document_write = DocumentWrite {
+            id: &doc.id,
+            name: &doc.name,
+            category: &doc.category,
+            content: &doc.content,
+            source_path: doc.source_path.as_deref(),
+            backup_path: doc.backup_path.as_deref(),
+            file_type: &doc.file_type,
+            created_at: &doc.created_at,
+            embedder_name: &embedder_name,
+            duration_ms: doc.duration_ms,
+            chunks: chunk_writes
+                .iter()
+                .map(|(index, text, start, end, start_ms, end_ms, embedding)| ChunkWrite {
+                    index: *index,
+                    text,
+                    start_offset: *start,
+                    end_offset: *end,
+                    start_ms: *start_ms,
+                    end_ms: *end_ms,
+                    embedding,
+                })
+                .collect(),
+        };
+        self.vector_db.commit_documents(&[document_write])?;
+
+        // 保存到内存中
+        self.documents.write().push(doc.clone());
+        Ok(doc)
+    }
+
+    /// 计算内容哈希，命中嵌入缓存（按哈希 + 嵌入器名称为 key）则直接复用，未命中才真正调用嵌入模型
+    fn embed_with_cache(&self, text: &str, embedder_name: &str, embedder: &Embedder) -> Result<Vec<f32>, KbError> {
+        let hash = crate::ai::vector_db::content_hash(text);
+        if let Some(cached) = self.vector_db.cache_get(&hash, embedder_name)? {
+            return Ok(cached);
+        }
+        let embedding = embedder.embed(text)?;
+        self.vector_db.cache_put(&hash, embedder_name, &embedding)?;
+        Ok(embedding)
+    }
+
+    /// 清理不再被任何文档/分块引用的嵌入缓存条目，返回删除的行数
+    pub async fn prune_cache(&self) -> Result<usize, KbError> {
+        Ok(self.vector_db.prune_cache()?)
+    }
+
+    /// 解析文档内容并完成源文件备份，但不做向量化与入库（供同步/异步两条路径复用）
+    ///
+    /// `duration_ms` 是源媒体总时长（毫秒），如视频转写文档；非时间轴文档传 `None`。
+    fn prepare_document(
+        &self, path: Option<&PathBuf>, content: Option<String>,
+        category: &str, backup_dir: Option<&PathBuf>, duration_ms: Option<u64>,
     ) -> Result<Document, KbError> {
         let doc_id = Uuid::new_v4().to_string();
 
@@ -175,45 +389,41 @@ impl KnowledgeBase {
             std::fs::create_dir_all(bdir).ok();
             if let Some(p) = path {
                 if p.exists() {
-                    // 备份原始文件  
+                    // 备份原始文件
                     let ext = p.extension().and_then(|e| e.to_str()).unwrap_or("bin");
-                    let backup_name = format!("{}_{}", &doc_id[..8], 
+                    let backup_name = format!("{}_{}", &doc_id[..8],
                         p.file_name().and_then(|n| n.to_str()).unwrap_or("file"));
                     let backup_file = bdir.join(&backup_name);
                     std::fs::copy(p, &backup_file).ok();
-                    
-                    // 如果是视频类型，额外保存转写文本
+
+                    // 如果是视频类型，额外保存转写文本（带时间戳时写成 WebVTT 字幕，保留分块的时间信息）
                     if category == "video-transcript" {
-                        let txt_name = format!("{}_{}.txt", &doc_id[..8], 
+                        let txt_name = format!("{}_{}.txt", &doc_id[..8],
                             p.file_stem().and_then(|n| n.to_str()).unwrap_or("video"));
                         let txt_file = bdir.join(&txt_name);
-                        std::fs::write(&txt_file, &final_content).ok();
+                        std::fs::write(&txt_file, transcript_backup_content(&final_content, duration_ms)).ok();
                     }
-                    
+
                     Some(backup_file.to_string_lossy().to_string())
                 } else {
                     // 没有源文件，只保存文本
                     let txt_name = format!("{}_transcript.txt", &doc_id[..8]);
                     let txt_file = bdir.join(&txt_name);
-                    std::fs::write(&txt_file, &final_content).ok();
+                    std::fs::write(&txt_file, transcript_backup_content(&final_content, duration_ms)).ok();
                     Some(txt_file.to_string_lossy().to_string())
                 }
             } else {
-                // 纯文本（如视频转写），保存为 txt 文件
+                // 纯文本（如视频转写），保存为 txt 文件（带时间戳时写成 WebVTT 字幕）
                 let txt_name = format!("{}_transcript.txt", &doc_id[..8]);
                 let txt_file = bdir.join(&txt_name);
-                std::fs::write(&txt_file, &final_content).ok();
+                std::fs::write(&txt_file, transcript_backup_content(&final_content, duration_ms)).ok();
                 Some(txt_file.to_string_lossy().to_string())
             }
         } else {
             None
         };
 
-        // 生成向量嵌入
-        let embedding = self.embedder.embed(&final_content)?;
-        self.vector_db.insert(&doc_id, &embedding)?;
-
-        let doc = Document {
+        Ok(Document {
             id: doc_id,
             name,
             category: category.to_string(),
@@ -222,20 +432,28 @@ impl KnowledgeBase {
             backup_path,
             file_type,
             created_at: Utc::now().to_rfc3339(),
-        };
-
-        // 保存文档元数据到数据库
-        self.vector_db.save_document(
-            &doc.id, &doc.name, &doc.category, &doc.content,
-            doc.source_path.as_deref(), doc.backup_path.as_deref(),
-            &doc.file_type, &doc.created_at,
-        )?;
+            duration_ms: duration_ms.map(|ms| ms as i64),
+        })
+    }
 
-        // 保存到内存中
-        self.documents.write().push(doc.clone());
+    /// 非阻塞地提交一篇文档：解析与备份立即完成，分块、向量化与入库交由后台 `EmbeddingQueue` 批量处理
+    ///
+    /// 返回的 `Document` 在 `flush` 完成前可能还未出现在 `list_documents`/`search` 结果中。
+    /// `duration_ms` 是源媒体总时长（毫秒），如视频转写时可提供，用于为分块估算时间戳。
+    pub fn enqueue_document(
+        &self, path: Option<&PathBuf>, content: Option<String>,
+        category: &str, backup_dir: Option<&PathBuf>, duration_ms: Option<u64>,
+    ) -> Result<Document, KbError> {
+        let doc = self.prepare_document(path, content, category, backup_dir, duration_ms)?;
+        self.embedding_queue.enqueue_document(doc.clone());
         Ok(doc)
     }
 
+    /// 等待已提交到后台队列的文档全部完成向量化与入库
+    pub async fn flush(&self) {
+        self.embedding_queue.flush().await;
+    }
+
     /// 解析文档内容
     fn parse_document(&self, path: &PathBuf) -> Result<String, KbError> {
         let extension = path
@@ -315,17 +533,65 @@ impl KnowledgeBase {
         Ok(text)
     }
 
-    /// 搜索相关知识
+    /// 搜索相关知识（默认语义/关键词各占一半权重的混合搜索，不限定分类）
     pub async fn search(&self, query: &str, limit: usize) -> Result<Vec<SearchResult>, KbError> {
-        let query_embedding = self.embedder.embed(query)?;
-        let similar_docs = self.vector_db.search(&query_embedding, limit)?;
+        self.search_hybrid(query, limit, 0.5, None).await
+    }
+
+    /// 混合搜索，`semantic_ratio` 控制语义检索相对关键词检索的权重占比（0.0 ~ 1.0）。
+    ///
+    /// `category` 指定时只使用该分类路由到的嵌入器做语义检索；不指定时在全部已注册的嵌入器下
+    /// 各自算一遍语义向量再融合排名，覆盖知识库中混有多个嵌入器产生的向量的情况。
+    pub async fn search_hybrid(
+        &self, query: &str, limit: usize, semantic_ratio: f32, category: Option<&str>,
+    ) -> Result<Vec<SearchResult>, KbError> {
+        let semantic_ratio = semantic_ratio.clamp(0.0, 1.0);
+
+        let query_embeddings: Vec<(String, Vec<f32>)> = match category {
+            Some(category) => {
+                let (embedder_name, embedder, _dimension) = self.embedders.resolve(category)?;
+                vec![(embedder_name, embedder.embed(query)?)]
+            }
+            None => {
+                let mut embeddings = Vec::new();
+                for info in self.embedders.list() {
+                    let (embedder, _dimension) = self.embedders.resolve_by_name(&info.name)?;
+                    embeddings.push((info.name, embedder.embed(query)?));
+                }
+                embeddings
+            }
+        };
+        let query_embeddings_ref: Vec<(&str, &[f32])> = query_embeddings
+            .iter()
+            .map(|(name, embedding)| (name.as_str(), embedding.as_slice()))
+            .collect();
+
+        let similar_docs = self.vector_db.search_hybrid_multi(
+            &query_embeddings_ref, query, limit, semantic_ratio, 1.0 - semantic_ratio,
+        )?;
+
+        // 各文档得分最高的分块（文本 + 时间戳），用于生成精准 snippet 并支持跳转回视频对应位置
+        // （旧文档无分块记录或非时间轴文档时分别为 None）
+        let best_chunks = self.vector_db.search_chunks_multi(&query_embeddings_ref, limit)?;
+        let mut best_chunk_text: std::collections::HashMap<String, String> = std::collections::HashMap::new();
+        let mut best_chunk_start_ms: std::collections::HashMap<String, i64> = std::collections::HashMap::new();
+        for (doc_id, text, _score, start_ms, _end_ms) in best_chunks {
+            if let Some(text) = text {
+                best_chunk_text.insert(doc_id.clone(), text);
+            }
+            if let Some(start_ms) = start_ms {
+                best_chunk_start_ms.insert(doc_id, start_ms);
+            }
+        }
 
         let documents = self.documents.read();
         let mut results = Vec::new();
 
         for (doc_id, relevance) in similar_docs {
             if let Some(doc) = documents.iter().find(|d| d.id == doc_id) {
-                let snippet = if doc.content.len() > 300 {
+                let snippet = if let Some(chunk_text) = best_chunk_text.get(&doc_id) {
+                    chunk_text.clone()
+                } else if doc.content.len() > 300 {
                     let char_boundary = doc.content.char_indices().nth(300).map(|(i, _)| i).unwrap_or(doc.content.len());
                     format!("{}...", &doc.content[..char_boundary])
                 } else {
@@ -336,6 +602,7 @@ impl KnowledgeBase {
                     document: doc.clone(),
                     relevance,
                     snippet,
+                    segment_start_ms: best_chunk_start_ms.get(&doc_id).copied(),
                 });
             }
         }
@@ -0,0 +1,7 @@
+pub mod chunking;
+pub mod embedder_registry;
+pub mod embedding_queue;
+pub mod hnsw;
+pub mod knowledge_base;
+pub mod service;
+pub mod vector_db;
@@ -1,8 +1,24 @@
+use crate::ai::hnsw::{HnswConfig, HnswIndex, HnswNode};
 use rusqlite::{params, Connection};
+use std::collections::HashMap;
 use std::path::Path;
 use std::sync::{Arc, Mutex};
 use thiserror::Error;
 
+/// Reciprocal Rank Fusion 的平滑常数，避免头部排名权重过大
+const RRF_K: f32 = 60.0;
+
+/// HNSW 索引中节点数达到该阈值后才启用近似搜索，数据量较小时暴力全扫描更准确也足够快
+const HNSW_MIN_NODES_FOR_ANN: usize = 50;
+
+/// 计算文本内容的 SHA-256 十六进制摘要，作为嵌入缓存的 key 组成部分
+pub fn content_hash(text: &str) -> String {
+    use sha2::{Digest, Sha256};
+    let mut hasher = Sha256::new();
+    hasher.update(text.as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
 #[derive(Error, Debug)]
 pub enum VectorDbError {
     #[error("数据库连接失败: {0}")]
@@ -11,12 +27,209 @@ pub enum VectorDbError {
     InsertFailed(String),
     #[error("向量搜索失败: {0}")]
     SearchFailed(String),
+    #[error("向量维度不匹配：期望 {expected}，实际 {actual}")]
+    DimensionMismatch { expected: usize, actual: usize },
     #[error("数据库错误: {0}")]
     DatabaseError(#[from] rusqlite::Error),
 }
 
 pub struct VectorDb {
     conn: Arc<Mutex<Connection>>,
+    /// 每个具名嵌入器一张独立的 HNSW 索引：不同嵌入器产生的向量空间互不可比，不能共用同一张图
+    hnsw: Mutex<HashMap<String, HnswIndex>>,
+    /// 串行化 `commit_documents` 的读-改-写全过程：该函数先短暂加锁 `hnsw` 取出快照、
+    /// 释放锁、在副本上变更、跑事务，最后才重新加锁把副本换回 `self.hnsw`——若两次调用
+    /// （如并发的两个“新增文档”请求）同时跑这套流程，后提交的一方会拿着基于更早快照算出
+    /// 的副本覆盖掉先提交的一方刚写入的节点，造成“更新丢失”。这把锁在函数入口获取、持有
+    /// 到函数返回，确保同一时刻至多一个调用在执行该序列。
+    write_lock: Mutex<()>,
+}
+
+/// 分块向量在 HNSW 索引中的 key：`document_id::chunk_index`，遗留整篇文档向量直接用 `document_id`
+fn hnsw_chunk_key(document_id: &str, chunk_index: usize) -> String {
+    format!("{}::{}", document_id, chunk_index)
+}
+
+/// 一条分块搜索候选：(文档 id, 命中的分块文本, 相似度, 分块在源媒体中的起止时间戳毫秒)
+type ChunkCandidate = (String, Option<String>, f32, Option<i64>, Option<i64>);
+
+/// 一个待提交分块的向量
+pub struct ChunkWrite<'a> {
+    pub index: usize,
+    pub text: &'a str,
+    pub start_offset: usize,
+    pub end_offset: usize,
+    /// 该分块在源媒体（如转写视频）中对应的时间范围，纯文本文档没有时间轴，始终为 `None`
+    pub start_ms: Option<i64>,
+    pub end_ms: Option<i64>,
+    pub embedding: &'a [f32],
+}
+
+/// 一篇待原子提交的文档（元数据 + 全部分块），用于后台批处理写入
+pub struct DocumentWrite<'a> {
+    pub id: &'a str,
+    pub name: &'a str,
+    pub category: &'a str,
+    pub content: &'a str,
+    pub source_path: Option<&'a str>,
+    pub backup_path: Option<&'a str>,
+    pub file_type: &'a str,
+    pub created_at: &'a str,
+    /// 本批次全部分块所使用的嵌入器名称（一篇文档按其分类路由到唯一的嵌入器）
+    pub embedder_name: &'a str,
+    /// 源媒体总时长（毫秒），非时间轴文档为 `None`
+    pub duration_ms: Option<i64>,
+    pub chunks: Vec<ChunkWrite<'a>>,
+}
+
+/// 从数据库重建全部具名嵌入器各自的 HNSW 索引，用于进程重启后恢复
+fn hnsw_load(conn: &Connection) -> Result<HashMap<String, HnswIndex>, VectorDbError> {
+    let mut nodes_by_embedder: HashMap<String, Vec<(u32, HnswNode)>> = HashMap::new();
+    {
+        let mut stmt = conn.prepare("SELECT embedder_name, node_id, key, level, embedding, dimension FROM hnsw_nodes")?;
+        let rows = stmt.query_map([], |row| {
+            let embedder_name: String = row.get(0)?;
+            let node_id: i64 = row.get(1)?;
+            let key: String = row.get(2)?;
+            let level: i64 = row.get(3)?;
+            let embedding_bytes: Vec<u8> = row.get(4)?;
+            let dimension: i64 = row.get(5)?;
+            Ok((embedder_name, node_id as u32, key, level as usize, embedding_bytes, dimension as usize))
+        })?;
+        for row in rows {
+            let (embedder_name, node_id, key, level, embedding_bytes, dimension) = row?;
+            let embedding = bytes_to_f32_slice(&embedding_bytes, dimension);
+            nodes_by_embedder.entry(embedder_name).or_default().push((
+                node_id,
+                HnswNode { key, embedding, level, neighbors: vec![Vec::new(); level + 1] },
+            ));
+        }
+    }
+
+    {
+        let mut stmt = conn.prepare("SELECT embedder_name, node_id, layer, neighbor_id FROM hnsw_edges")?;
+        let rows = stmt.query_map([], |row| {
+            let embedder_name: String = row.get(0)?;
+            let node_id: i64 = row.get(1)?;
+            let layer: i64 = row.get(2)?;
+            let neighbor_id: i64 = row.get(3)?;
+            Ok((embedder_name, node_id as u32, layer as usize, neighbor_id as u32))
+        })?;
+        // 按 embedder_name 分组后再建立 node_id -> &mut HnswNode 的映射，避免跨嵌入器串图
+        let mut by_embedder: HashMap<&str, HashMap<u32, &mut HnswNode>> = HashMap::new();
+        for (embedder_name, nodes) in nodes_by_embedder.iter_mut() {
+            by_embedder.insert(embedder_name.as_str(), nodes.iter_mut().map(|(id, node)| (*id, node)).collect());
+        }
+        for row in rows {
+            let (embedder_name, node_id, layer, neighbor_id) = row?;
+            if let Some(nodes) = by_embedder.get_mut(embedder_name.as_str()) {
+                if let Some(node) = nodes.get_mut(&node_id) {
+                    if node.neighbors.len() <= layer {
+                        node.neighbors.resize(layer + 1, Vec::new());
+                    }
+                    node.neighbors[layer].push(neighbor_id);
+                }
+            }
+        }
+    }
+
+    let mut indexes = HashMap::new();
+    for (embedder_name, nodes) in nodes_by_embedder {
+        let next_id = hnsw_meta_get(conn, &embedder_name, "next_id")?.and_then(|v| v.parse::<u32>().ok()).unwrap_or(0);
+        let entry_point = hnsw_meta_get(conn, &embedder_name, "entry_point")?.and_then(|v| v.parse::<u32>().ok());
+        indexes.insert(embedder_name, HnswIndex::restore(HnswConfig::default(), nodes, next_id, entry_point));
+    }
+    Ok(indexes)
+}
+
+/// 将某个嵌入器的 HNSW 索引全量写入（先清空该嵌入器的行再写入）到给定连接，不自行加锁也不自行
+/// 提交——调用方既可以传入独立连接（如 [`VectorDb::hnsw_persist`]），也可以传入一个尚未提交的
+/// [`rusqlite::Transaction`]（通过 `Deref` 强转为 `&Connection`），让 HNSW 快照与其他 SQL 写入
+/// 共享同一次提交，避免“行写进去了但索引快照没落盘”的崩溃窗口。
+///
+/// 只接收已经取出的快照数据而非 `&HnswIndex` 本身，这样调用方可以先锁 `hnsw` 取完快照、
+/// 释放该锁，再锁 `conn` 写入，全程不需要同时持有两把锁，维持本文件一贯“从不同时持有
+/// conn 和 hnsw 两把锁”的约定，避免不同调用路径以相反顺序加锁导致死锁。
+fn hnsw_persist_rows(
+    conn: &Connection, embedder_name: &str, nodes: &[(u32, HnswNode)], next_id: u32, entry_point: Option<u32>,
+) -> Result<(), VectorDbError> {
+    conn.execute("DELETE FROM hnsw_nodes WHERE embedder_name = ?1", params![embedder_name])?;
+    conn.execute("DELETE FROM hnsw_edges WHERE embedder_name = ?1", params![embedder_name])?;
+    conn.execute("DELETE FROM hnsw_meta WHERE embedder_name = ?1", params![embedder_name])?;
+
+    for (node_id, node) in nodes {
+        let embedding_bytes = f32_slice_to_bytes(&node.embedding);
+        conn.execute(
+            "INSERT INTO hnsw_nodes (embedder_name, node_id, key, level, embedding, dimension) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![embedder_name, node_id, node.key, node.level as i64, embedding_bytes, node.embedding.len() as i64],
+        )?;
+        for (layer, neighbors) in node.neighbors.iter().enumerate() {
+            for &neighbor_id in neighbors {
+                conn.execute(
+                    "INSERT OR IGNORE INTO hnsw_edges (embedder_name, node_id, layer, neighbor_id) VALUES (?1, ?2, ?3, ?4)",
+                    params![embedder_name, node_id, layer as i64, neighbor_id],
+                )?;
+            }
+        }
+    }
+
+    conn.execute(
+        "INSERT INTO hnsw_meta (embedder_name, key, value) VALUES (?1, 'next_id', ?2)",
+        params![embedder_name, next_id.to_string()],
+    )?;
+    if let Some(entry_point) = entry_point {
+        conn.execute(
+            "INSERT INTO hnsw_meta (embedder_name, key, value) VALUES (?1, 'entry_point', ?2)",
+            params![embedder_name, entry_point.to_string()],
+        )?;
+    }
+
+    Ok(())
+}
+
+fn hnsw_meta_get(conn: &Connection, embedder_name: &str, key: &str) -> Result<Option<String>, VectorDbError> {
+    let mut stmt = conn.prepare("SELECT value FROM hnsw_meta WHERE embedder_name = ?1 AND key = ?2")?;
+    let mut rows = stmt.query_map(params![embedder_name, key], |row| row.get::<_, String>(0))?;
+    match rows.next() {
+        Some(value) => Ok(Some(value?)),
+        None => Ok(None),
+    }
+}
+
+fn f32_slice_to_bytes(slice: &[f32]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(slice.len() * 4);
+    for &value in slice {
+        bytes.extend_from_slice(&value.to_le_bytes());
+    }
+    bytes
+}
+
+fn bytes_to_f32_slice(bytes: &[u8], dimension: usize) -> Vec<f32> {
+    let mut result = Vec::with_capacity(dimension);
+    for i in 0..dimension {
+        let start = i * 4;
+        let end = start + 4;
+        if end <= bytes.len() {
+            let bytes_array: [u8; 4] = bytes[start..end].try_into().unwrap();
+            result.push(f32::from_le_bytes(bytes_array));
+        }
+    }
+    result
+}
+
+/// 余弦相似度。输入向量维度不一致时返回 [`VectorDbError::DimensionMismatch`] 而非静默当作 0 分，
+/// 因为这通常意味着查询向量与存储行使用了不同的嵌入器，调用方应先按 `embedder_name` 过滤。
+fn cosine_similarity(a: &[f32], b: &[f32]) -> Result<f32, VectorDbError> {
+    if a.len() != b.len() {
+        return Err(VectorDbError::DimensionMismatch { expected: a.len(), actual: b.len() });
+    }
+    let dot_product: f32 = a.iter().zip(b.iter()).map(|(x, y)| x * y).sum();
+    let norm_a: f32 = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b: f32 = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return Ok(0.0);
+    }
+    Ok(dot_product / (norm_a * norm_b))
 }
 
 impl VectorDb {
@@ -54,6 +267,10 @@ impl VectorDb {
         // 数据库迁移：为旧表添加新列（如果不存在）
         let _ = conn.execute("ALTER TABLE documents ADD COLUMN backup_path TEXT", []);
         let _ = conn.execute("ALTER TABLE documents ADD COLUMN file_type TEXT NOT NULL DEFAULT ''", []);
+        // 视频等带时间轴的源文档记录总时长，用于把分块的字符偏移换算成大致的时间戳
+        let _ = conn.execute("ALTER TABLE documents ADD COLUMN duration_ms INTEGER", []);
+        // 每个向量行记录产生它的嵌入器名称，搜索时据此过滤，避免跨嵌入器语义空间误比较
+        let _ = conn.execute("ALTER TABLE document_vectors ADD COLUMN embedder_name TEXT NOT NULL DEFAULT ''", []);
 
         // 创建索引以加速搜索
         conn.execute(
@@ -61,28 +278,98 @@ impl VectorDb {
             [],
         )?;
 
+        // 创建 FTS5 虚拟表用于关键词检索，与 documents 表手动保持同步
+        conn.execute(
+            "CREATE VIRTUAL TABLE IF NOT EXISTS documents_fts USING fts5(document_id UNINDEXED, content)",
+            [],
+        )?;
+
+        // 分块向量表：长文档被切分为多个重叠的段落分别嵌入，取代整篇文档一个向量的做法
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS document_chunks (
+                document_id TEXT NOT NULL,
+                chunk_index INTEGER NOT NULL,
+                chunk_text TEXT NOT NULL,
+                start_offset INTEGER NOT NULL,
+                end_offset INTEGER NOT NULL,
+                embedding BLOB NOT NULL,
+                dimension INTEGER NOT NULL,
+                created_at TEXT NOT NULL,
+                PRIMARY KEY (document_id, chunk_index)
+            )",
+            [],
+        )?;
+        let _ = conn.execute("ALTER TABLE document_chunks ADD COLUMN embedder_name TEXT NOT NULL DEFAULT ''", []);
+        // 分块在源媒体中对应的时间范围（毫秒），非时间轴文档（普通文本等）始终为 NULL
+        let _ = conn.execute("ALTER TABLE document_chunks ADD COLUMN start_ms INTEGER", []);
+        let _ = conn.execute("ALTER TABLE document_chunks ADD COLUMN end_ms INTEGER", []);
+
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_chunk_document_id ON document_chunks(document_id)",
+            [],
+        )?;
+
+        // 嵌入缓存：按内容哈希 + 嵌入器身份为键，重新入库未变化的文件或重启后重建索引时跳过重复向量化
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS embedding_cache (
+                content_hash TEXT NOT NULL,
+                embedder_id TEXT NOT NULL,
+                embedding BLOB NOT NULL,
+                dimension INTEGER NOT NULL,
+                created_at TEXT NOT NULL,
+                PRIMARY KEY (content_hash, embedder_id)
+            )",
+            [],
+        )?;
+
+        // HNSW 近似最近邻索引的持久化快照：节点（含向量）、各层边、以及入口点/自增 id 等元信息，
+        // 均以 embedder_name 分区，因为不同嵌入器的向量空间不能共享同一张图
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS hnsw_nodes (
+                embedder_name TEXT NOT NULL,
+                node_id INTEGER NOT NULL,
+                key TEXT NOT NULL,
+                level INTEGER NOT NULL,
+                embedding BLOB NOT NULL,
+                dimension INTEGER NOT NULL,
+                PRIMARY KEY (embedder_name, node_id)
+            )",
+            [],
+        )?;
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS hnsw_edges (
+                embedder_name TEXT NOT NULL,
+                node_id INTEGER NOT NULL,
+                layer INTEGER NOT NULL,
+                neighbor_id INTEGER NOT NULL,
+                PRIMARY KEY (embedder_name, node_id, layer, neighbor_id)
+            )",
+            [],
+        )?;
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS hnsw_meta (
+                embedder_name TEXT NOT NULL,
+                key TEXT NOT NULL,
+                value TEXT NOT NULL,
+                PRIMARY KEY (embedder_name, key)
+            )",
+            [],
+        )?;
+
+        let hnsw = hnsw_load(&conn)?;
+
         Ok(Self {
             conn: Arc::new(Mutex::new(conn)),
+            hnsw: Mutex::new(hnsw),
+            write_lock: Mutex::new(()),
         })
     }
 
-    /// 保存文档元数据
-    pub fn save_document(
-        &self, id: &str, name: &str, category: &str, content: &str,
-        source_path: Option<&str>, backup_path: Option<&str>, file_type: &str, created_at: &str,
-    ) -> Result<(), VectorDbError> {
-        let conn = self.conn.lock().unwrap();
-        conn.execute(
-            "INSERT OR REPLACE INTO documents (id, name, category, content, source_path, backup_path, file_type, created_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
-            params![id, name, category, content, source_path, backup_path, file_type, created_at],
-        ).map_err(|e| VectorDbError::InsertFailed(e.to_string()))?;
-        Ok(())
-    }
-
     /// 加载所有文档元数据
-    pub fn load_documents(&self) -> Result<Vec<(String, String, String, String, Option<String>, Option<String>, String, String)>, VectorDbError> {
+    #[allow(clippy::type_complexity)]
+    pub fn load_documents(&self) -> Result<Vec<(String, String, String, String, Option<String>, Option<String>, String, String, Option<i64>)>, VectorDbError> {
         let conn = self.conn.lock().unwrap();
-        let mut stmt = conn.prepare("SELECT id, name, category, content, source_path, backup_path, file_type, created_at FROM documents")?;
+        let mut stmt = conn.prepare("SELECT id, name, category, content, source_path, backup_path, file_type, created_at, duration_ms FROM documents")?;
         let rows = stmt.query_map([], |row| {
             Ok((
                 row.get::<_, String>(0)?,
@@ -93,9 +380,10 @@ impl VectorDb {
                 row.get::<_, Option<String>>(5)?,
                 row.get::<_, String>(6).unwrap_or_default(),
                 row.get::<_, String>(7)?,
+                row.get::<_, Option<i64>>(8).unwrap_or(None),
             ))
         })?;
-        
+
         let mut docs = Vec::new();
         for row in rows {
             docs.push(row?);
@@ -107,110 +395,724 @@ impl VectorDb {
     pub fn delete_document(&self, id: &str) -> Result<(), VectorDbError> {
         let conn = self.conn.lock().unwrap();
         conn.execute("DELETE FROM documents WHERE id = ?1", params![id])?;
+        conn.execute("DELETE FROM documents_fts WHERE document_id = ?1", params![id])?;
         Ok(())
     }
 
-    /// 插入向量
-    pub fn insert(&self, document_id: &str, embedding: &[f32]) -> Result<(), VectorDbError> {
-        let embedding_bytes = self.f32_slice_to_bytes(embedding);
-        let dimension = embedding.len() as i32;
-        let created_at = chrono::Utc::now().to_rfc3339();
+    /// 删除某文档的全部分块
+    pub fn delete_chunks(&self, document_id: &str) -> Result<(), VectorDbError> {
+        let chunk_keys: Vec<(i64, String)> = {
+            let conn = self.conn.lock().unwrap();
+            let mut stmt = conn.prepare("SELECT chunk_index, embedder_name FROM document_chunks WHERE document_id = ?1")?;
+            let rows = stmt.query_map(params![document_id], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+            })?;
+            rows.collect::<Result<Vec<_>, _>>()?
+        };
 
-        let conn = self.conn.lock().unwrap();
-        conn.execute(
-            "INSERT OR REPLACE INTO document_vectors (document_id, embedding, dimension, created_at)
-             VALUES (?1, ?2, ?3, ?4)",
-            params![document_id, embedding_bytes, dimension, created_at],
-        ).map_err(|e| VectorDbError::InsertFailed(e.to_string()))?;
+        {
+            let conn = self.conn.lock().unwrap();
+            conn.execute(
+                "DELETE FROM document_chunks WHERE document_id = ?1",
+                params![document_id],
+            )?;
+        }
+
+        let mut touched_embedders: Vec<String> = Vec::new();
+        {
+            let mut hnsw = self.hnsw.lock().unwrap();
+            for (chunk_index, embedder_name) in &chunk_keys {
+                if let Some(index) = hnsw.get_mut(embedder_name) {
+                    index.remove(&hnsw_chunk_key(document_id, *chunk_index as usize));
+                }
+                if !touched_embedders.contains(embedder_name) {
+                    touched_embedders.push(embedder_name.clone());
+                }
+            }
+        }
+        for embedder_name in touched_embedders {
+            self.hnsw_persist(&embedder_name)?;
+        }
 
         Ok(())
     }
 
-    /// 搜索相似向量（使用余弦相似度）
+    /// 在分块向量上做相似度搜索，按文档去重只保留每个文档得分最高的分块
+    ///
+    /// 只比较 `embedder_name` 相同的行。数据量达到 [`HNSW_MIN_NODES_FOR_ANN`] 后走 HNSW
+    /// 近似搜索，否则回退到暴力全扫描保证小数据量下的正确性。暴力全扫描路径把没有分块记录的旧文档
+    /// （仅有 `document_vectors` 单一向量）当作只有一个分块处理；HNSW 索引只收录分块向量，
+    /// 走近似搜索时这类旧文档不会出现在结果中。
+    fn search_chunk_candidates(
+        &self, query_embedding: &[f32], limit: usize, embedder_name: &str,
+    ) -> Result<Vec<ChunkCandidate>, VectorDbError> {
+        if self.hnsw_len(embedder_name) >= HNSW_MIN_NODES_FOR_ANN {
+            self.search_chunk_candidates_ann(query_embedding, limit, embedder_name)
+        } else {
+            self.search_chunk_candidates_bruteforce(query_embedding, limit, embedder_name)
+        }
+    }
+
+    /// 使用 HNSW 索引做近似最近邻查询，按文档去重只保留每个文档得分最高的命中
+    fn search_chunk_candidates_ann(
+        &self, query_embedding: &[f32], limit: usize, embedder_name: &str,
+    ) -> Result<Vec<ChunkCandidate>, VectorDbError> {
+        // 扩大候选池再按文档去重截断，避免同一文档的多个分块挤占了本应属于其他文档的名额
+        let pool = {
+            let hnsw = self.hnsw.lock().unwrap();
+            match hnsw.get(embedder_name) {
+                Some(index) => index.search(query_embedding, limit.max(1) * 8),
+                None => Vec::new(),
+            }
+        };
+
+        let conn = self.conn.lock().unwrap();
+        let mut best: HashMap<String, (Option<String>, f32, Option<i64>, Option<i64>)> = HashMap::new();
+
+        for (key, score) in pool {
+            let (document_id, chunk_index) = match key.split_once("::") {
+                Some((document_id, chunk_index)) => (document_id.to_string(), chunk_index.parse::<i64>().ok()),
+                None => (key, None),
+            };
+
+            let chunk_row = match chunk_index {
+                Some(chunk_index) => conn
+                    .query_row(
+                        "SELECT chunk_text, start_ms, end_ms FROM document_chunks WHERE document_id = ?1 AND chunk_index = ?2",
+                        params![document_id, chunk_index],
+                        |row| Ok((row.get::<_, String>(0)?, row.get::<_, Option<i64>>(1)?, row.get::<_, Option<i64>>(2)?)),
+                    )
+                    .ok(),
+                None => None,
+            };
+            let (chunk_text, start_ms, end_ms) = match chunk_row {
+                Some((text, start_ms, end_ms)) => (Some(text), start_ms, end_ms),
+                None => (None, None, None),
+            };
+
+            best.entry(document_id)
+                .and_modify(|(text, best_score, best_start, best_end)| {
+                    if score > *best_score {
+                        *text = chunk_text.clone();
+                        *best_score = score;
+                        *best_start = start_ms;
+                        *best_end = end_ms;
+                    }
+                })
+                .or_insert((chunk_text, score, start_ms, end_ms));
+        }
+
+        let mut results: Vec<ChunkCandidate> = best
+            .into_iter()
+            .map(|(id, (text, score, start_ms, end_ms))| (id, text, score, start_ms, end_ms))
+            .collect();
+        results.sort_by(|a, b| b.2.partial_cmp(&a.2).unwrap_or(std::cmp::Ordering::Equal));
+        results.truncate(limit);
+        Ok(results)
+    }
+
+    /// 暴力全扫描做余弦相似度搜索，按文档去重只保留每个文档得分最高的分块
+    ///
+    /// 只比较 `embedder_name` 相同的行，没有分块记录的旧文档（仅有 `document_vectors` 单一向量）
+    /// 被当作只有一个分块处理，以保持兼容。
+    fn search_chunk_candidates_bruteforce(
+        &self, query_embedding: &[f32], limit: usize, embedder_name: &str,
+    ) -> Result<Vec<ChunkCandidate>, VectorDbError> {
+        let conn = self.conn.lock().unwrap();
+
+        let mut best: HashMap<String, (Option<String>, f32, Option<i64>, Option<i64>)> = HashMap::new();
+
+        // 分块向量
+        {
+            let mut stmt = conn
+                .prepare("SELECT document_id, chunk_text, embedding, dimension, start_ms, end_ms FROM document_chunks WHERE embedder_name = ?1")
+                .map_err(|e| VectorDbError::SearchFailed(e.to_string()))?;
+            let rows = stmt
+                .query_map(params![embedder_name], |row| {
+                    let document_id: String = row.get(0)?;
+                    let chunk_text: String = row.get(1)?;
+                    let embedding_bytes: Vec<u8> = row.get(2)?;
+                    let dimension: i32 = row.get(3)?;
+                    let start_ms: Option<i64> = row.get(4)?;
+                    let end_ms: Option<i64> = row.get(5)?;
+                    Ok((document_id, chunk_text, embedding_bytes, dimension, start_ms, end_ms))
+                })
+                .map_err(|e| VectorDbError::SearchFailed(e.to_string()))?;
+
+            for row in rows {
+                let (document_id, chunk_text, embedding_bytes, dimension, start_ms, end_ms) =
+                    row.map_err(|e| VectorDbError::SearchFailed(e.to_string()))?;
+                let embedding = bytes_to_f32_slice(&embedding_bytes, dimension as usize);
+                let similarity = cosine_similarity(query_embedding, &embedding)?;
+
+                best.entry(document_id)
+                    .and_modify(|(text, score, best_start, best_end)| {
+                        if similarity > *score {
+                            *text = Some(chunk_text.clone());
+                            *score = similarity;
+                            *best_start = start_ms;
+                            *best_end = end_ms;
+                        }
+                    })
+                    .or_insert((Some(chunk_text), similarity, start_ms, end_ms));
+            }
+        }
+
+        // 遗留的整篇文档单一向量（尚未被分块化的旧数据），仅当该文档没有任何分块时才参与
+        {
+            let mut stmt = conn
+                .prepare("SELECT document_id, embedding, dimension FROM document_vectors WHERE embedder_name = ?1")
+                .map_err(|e| VectorDbError::SearchFailed(e.to_string()))?;
+            let rows = stmt
+                .query_map(params![embedder_name], |row| {
+                    let document_id: String = row.get(0)?;
+                    let embedding_bytes: Vec<u8> = row.get(1)?;
+                    let dimension: i32 = row.get(2)?;
+                    Ok((document_id, embedding_bytes, dimension))
+                })
+                .map_err(|e| VectorDbError::SearchFailed(e.to_string()))?;
+
+            for row in rows {
+                let (document_id, embedding_bytes, dimension) =
+                    row.map_err(|e| VectorDbError::SearchFailed(e.to_string()))?;
+                if best.contains_key(&document_id) {
+                    continue;
+                }
+                let embedding = bytes_to_f32_slice(&embedding_bytes, dimension as usize);
+                let similarity = cosine_similarity(query_embedding, &embedding)?;
+                best.insert(document_id, (None, similarity, None, None));
+            }
+        }
+
+        let mut results: Vec<ChunkCandidate> = best
+            .into_iter()
+            .map(|(id, (text, score, start_ms, end_ms))| (id, text, score, start_ms, end_ms))
+            .collect();
+        results.sort_by(|a, b| b.2.partial_cmp(&a.2).unwrap_or(std::cmp::Ordering::Equal));
+        results.truncate(limit);
+        Ok(results)
+    }
+
+    /// 搜索相似向量（使用余弦相似度），内部基于分块匹配并按文档去重
+    ///
+    /// `embedder_name` 必须与生成 `query_embedding` 的嵌入器一致，搜索只比较该嵌入器产生的向量。
     pub fn search(
         &self,
         query_embedding: &[f32],
         limit: usize,
+        embedder_name: &str,
     ) -> Result<Vec<(String, f32)>, VectorDbError> {
+        let candidates = self.search_chunk_candidates(query_embedding, limit, embedder_name)?;
+        Ok(candidates.into_iter().map(|(id, _, score, _, _)| (id, score)).collect())
+    }
+
+    /// 搜索相似分块，同时返回命中的分块文本与其在源媒体中的起止时间戳（毫秒），供调用方生成精准
+    /// snippet 以及深链跳转位置
+    ///
+    /// 旧文档（无分块记录）或非时间轴文档返回的分块文本/时间戳为 `None`。
+    pub fn search_chunks(
+        &self, query_embedding: &[f32], limit: usize, embedder_name: &str,
+    ) -> Result<Vec<ChunkCandidate>, VectorDbError> {
+        self.search_chunk_candidates(query_embedding, limit, embedder_name)
+    }
+
+    /// 关键词搜索（FTS5 MATCH），按 FTS5 自带的 rank 排序
+    fn search_keyword(&self, query_text: &str, limit: usize) -> Result<Vec<String>, VectorDbError> {
+        // 按空白切分成独立词项、各自转义后用 AND 连接，而不是把整个查询包成一个短语——
+        // 短语匹配要求各词在原文中连续且顺序一致，绝大多数自然语言查询的词序和位置都对不上，
+        // 导致关键词一路常年命中为空、混合搜索悄悄退化成只有语义一路。每个词项仍然单独加双引号，
+        // 使其被当作字面 token 匹配，不会被解释成列过滤器或 `-`/`OR`/`NEAR` 等 FTS5 查询算符。
+        let match_query = query_text
+            .split_whitespace()
+            .map(|term| format!("\"{}\"", term.replace('"', "\"\"")))
+            .collect::<Vec<_>>()
+            .join(" AND ");
+        if match_query.is_empty() {
+            return Ok(Vec::new());
+        }
+
         let conn = self.conn.lock().unwrap();
         let mut stmt = conn
-            .prepare("SELECT document_id, embedding, dimension FROM document_vectors")
+            .prepare("SELECT document_id FROM documents_fts WHERE documents_fts MATCH ?1 ORDER BY rank LIMIT ?2")
             .map_err(|e| VectorDbError::SearchFailed(e.to_string()))?;
 
         let rows = stmt
-            .query_map([], |row| {
-                let document_id: String = row.get(0)?;
-                let embedding_bytes: Vec<u8> = row.get(1)?;
-                let dimension: i32 = row.get(2)?;
-                Ok((document_id, embedding_bytes, dimension))
-            })
+            .query_map(params![match_query, limit as i64], |row| row.get::<_, String>(0))
             .map_err(|e| VectorDbError::SearchFailed(e.to_string()))?;
 
-        let mut results = Vec::new();
-
+        let mut ids = Vec::new();
         for row in rows {
-            let (document_id, embedding_bytes, dimension) =
-                row.map_err(|e| VectorDbError::SearchFailed(e.to_string()))?;
+            ids.push(row?);
+        }
+        Ok(ids)
+    }
+
+    /// 混合搜索：融合向量余弦相似度与 FTS5 关键词检索，使用 Reciprocal Rank Fusion 合并排名
+    ///
+    /// `semantic_weight`/`keyword_weight` 用于调节两路排名各自的贡献占比，`embedder_name` 指定
+    /// 语义检索一路所使用的嵌入器（关键词检索与嵌入器无关）。
+    pub fn search_hybrid(
+        &self,
+        query_embedding: &[f32],
+        query_text: &str,
+        limit: usize,
+        semantic_weight: f32,
+        keyword_weight: f32,
+        embedder_name: &str,
+    ) -> Result<Vec<(String, f32)>, VectorDbError> {
+        self.search_hybrid_multi(&[(embedder_name, query_embedding)], query_text, limit, semantic_weight, keyword_weight)
+    }
+
+    /// 混合搜索的多嵌入器版本：同一个查询文本在每个具名嵌入器下各自算出的语义向量都参与融合，
+    /// 连同关键词检索一起用 RRF 合并排名。不限定分类时（知识库中混有多个嵌入器产生的文档）使用。
+    pub fn search_hybrid_multi(
+        &self,
+        query_embeddings: &[(&str, &[f32])],
+        query_text: &str,
+        limit: usize,
+        semantic_weight: f32,
+        keyword_weight: f32,
+    ) -> Result<Vec<(String, f32)>, VectorDbError> {
+        // 各取一个较宽的候选池再融合，避免排名截断过早导致漏掉互补结果
+        let pool_size = limit.max(20) * 4;
 
-            let embedding = self.bytes_to_f32_slice(&embedding_bytes, dimension as usize);
-            let similarity = self.cosine_similarity(query_embedding, &embedding);
+        let mut fused: HashMap<String, f32> = HashMap::new();
+        for (embedder_name, query_embedding) in query_embeddings {
+            let semantic_ranked = self.search(query_embedding, pool_size, embedder_name)?;
+            for (rank, (document_id, _)) in semantic_ranked.iter().enumerate() {
+                let contribution = semantic_weight / (RRF_K + (rank + 1) as f32);
+                *fused.entry(document_id.clone()).or_insert(0.0) += contribution;
+            }
+        }
 
-            results.push((document_id, similarity));
+        let keyword_ranked = self.search_keyword(query_text, pool_size)?;
+        for (rank, document_id) in keyword_ranked.iter().enumerate() {
+            let contribution = keyword_weight / (RRF_K + (rank + 1) as f32);
+            *fused.entry(document_id.clone()).or_insert(0.0) += contribution;
         }
 
+        let mut results: Vec<(String, f32)> = fused.into_iter().collect();
         results.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
-        Ok(results.into_iter().take(limit).collect())
+        results.truncate(limit);
+        Ok(results)
     }
 
-    /// 删除向量
-    pub fn delete(&self, document_id: &str) -> Result<(), VectorDbError> {
-        let conn = self.conn.lock().unwrap();
-        conn.execute(
-            "DELETE FROM document_vectors WHERE document_id = ?1",
-            params![document_id],
-        )?;
+    /// 在多个具名嵌入器的分块索引上各自搜索，按文档去重合并为每个文档得分最高的一条命中，
+    /// 用于不限定分类的全局搜索场景下生成 snippet。
+    pub fn search_chunks_multi(
+        &self, query_embeddings: &[(&str, &[f32])], limit: usize,
+    ) -> Result<Vec<ChunkCandidate>, VectorDbError> {
+        let mut best: HashMap<String, (Option<String>, f32, Option<i64>, Option<i64>)> = HashMap::new();
+        for (embedder_name, query_embedding) in query_embeddings {
+            for (document_id, text, score, start_ms, end_ms) in
+                self.search_chunk_candidates(query_embedding, limit, embedder_name)?
+            {
+                best.entry(document_id)
+                    .and_modify(|(best_text, best_score, best_start, best_end)| {
+                        if score > *best_score {
+                            *best_text = text.clone();
+                            *best_score = score;
+                            *best_start = start_ms;
+                            *best_end = end_ms;
+                        }
+                    })
+                    .or_insert((text, score, start_ms, end_ms));
+            }
+        }
+        let mut results: Vec<ChunkCandidate> = best
+            .into_iter()
+            .map(|(id, (text, score, start_ms, end_ms))| (id, text, score, start_ms, end_ms))
+            .collect();
+        results.sort_by(|a, b| b.2.partial_cmp(&a.2).unwrap_or(std::cmp::Ordering::Equal));
+        results.truncate(limit);
+        Ok(results)
+    }
+
+    /// 在一次事务中原子提交多篇文档（元数据 + FTS 索引 + 全部分块向量）
+    ///
+    /// 供后台批处理队列使用：要么整批写入全部生效，要么在出错时全部回滚，
+    /// 避免崩溃或部分失败导致文档行缺少对应向量。
+    ///
+    /// 本函数内部对 `self.hnsw` 采取“读快照 -> 在副本上改 -> 提交事务 -> 换回”的流程，
+    /// 中途会短暂释放 `hnsw` 锁（见下文）。若不加额外同步，两个并发调用会各自基于
+    /// 提交前的旧快照计算出副本，后提交的一方换回副本时会覆盖掉先提交的一方刚写入的节点，
+    /// 造成更新丢失。`write_lock` 把整个读-改-写序列串行化，持有到函数返回为止。
+    pub fn commit_documents(&self, items: &[DocumentWrite]) -> Result<(), VectorDbError> {
+        let _write_guard = self.write_lock.lock().unwrap();
+
+        // 记录每篇文档写入前的旧分块 key（按其旧 embedder_name 分组），写入成功后用于清理 HNSW 索引，
+        // 覆盖“reindex 切换了嵌入器”导致旧向量散落在另一张图里的情况
+        let mut old_chunks_by_embedder: HashMap<String, Vec<String>> = HashMap::new();
+        {
+            let conn = self.conn.lock().unwrap();
+            for item in items {
+                let mut stmt = conn.prepare("SELECT chunk_index, embedder_name FROM document_chunks WHERE document_id = ?1")?;
+                let rows: Vec<(i64, String)> = stmt
+                    .query_map(params![item.id], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)))?
+                    .collect::<Result<Vec<_>, _>>()?;
+                for (chunk_index, embedder_name) in rows {
+                    old_chunks_by_embedder
+                        .entry(embedder_name)
+                        .or_default()
+                        .push(hnsw_chunk_key(item.id, chunk_index as usize));
+                }
+            }
+        }
+
+        // 在克隆出的索引副本上完成内存更新，而不是直接改 `self.hnsw`：这样即便下面的事务
+        // 因故失败，self.hnsw 也不会领先于（回滚后的）数据库——只有 `tx.commit()` 成功后，
+        // 才把这些副本换回 `self.hnsw`。取完副本后立刻释放 `hnsw` 锁再去锁 `conn` 开事务，
+        // 维持本文件一贯“从不同时持有 conn 和 hnsw 两把锁”的约定，避免死锁。
+        let mut pending: HashMap<String, HnswIndex> = HashMap::new();
+        {
+            let hnsw = self.hnsw.lock().unwrap();
+            let mut touch = |name: &str, pending: &mut HashMap<String, HnswIndex>| {
+                pending
+                    .entry(name.to_string())
+                    .or_insert_with(|| hnsw.get(name).cloned().unwrap_or_else(|| HnswIndex::new(HnswConfig::default())));
+            };
+            for embedder_name in old_chunks_by_embedder.keys() {
+                touch(embedder_name, &mut pending);
+            }
+            for item in items {
+                touch(item.embedder_name, &mut pending);
+            }
+        }
+        for (embedder_name, keys) in &old_chunks_by_embedder {
+            if let Some(index) = pending.get_mut(embedder_name) {
+                for key in keys {
+                    index.remove(key);
+                }
+            }
+        }
+        for item in items {
+            let index = pending.get_mut(item.embedder_name).expect("touched above");
+            for chunk in &item.chunks {
+                index.insert(hnsw_chunk_key(item.id, chunk.index), chunk.embedding.to_vec());
+            }
+        }
+        let touched_snapshots: Vec<(String, Vec<(u32, HnswNode)>, u32, Option<u32>)> = pending
+            .iter()
+            .map(|(name, index)| (name.clone(), index.nodes_snapshot(), index.next_id(), index.entry_point()))
+            .collect();
+
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn
+            .transaction()
+            .map_err(|e| VectorDbError::InsertFailed(e.to_string()))?;
+
+        for item in items {
+            tx.execute(
+                "INSERT OR REPLACE INTO documents (id, name, category, content, source_path, backup_path, file_type, created_at, duration_ms) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                params![item.id, item.name, item.category, item.content, item.source_path, item.backup_path, item.file_type, item.created_at, item.duration_ms],
+            ).map_err(|e| VectorDbError::InsertFailed(e.to_string()))?;
+
+            tx.execute("DELETE FROM documents_fts WHERE document_id = ?1", params![item.id])?;
+            tx.execute(
+                "INSERT INTO documents_fts (document_id, content) VALUES (?1, ?2)",
+                params![item.id, item.content],
+            ).map_err(|e| VectorDbError::InsertFailed(e.to_string()))?;
+
+            tx.execute("DELETE FROM document_chunks WHERE document_id = ?1", params![item.id])?;
+            for chunk in &item.chunks {
+                let embedding_bytes = f32_slice_to_bytes(chunk.embedding);
+                let dimension = chunk.embedding.len() as i32;
+                let created_at = chrono::Utc::now().to_rfc3339();
+                tx.execute(
+                    "INSERT OR REPLACE INTO document_chunks
+                        (document_id, chunk_index, chunk_text, start_offset, end_offset, embedding, dimension, created_at, embedder_name, start_ms, end_ms)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+                    params![
+                        item.id, chunk.index as i64, chunk.text,
+                        chunk.start_offset as i64, chunk.end_offset as i64, embedding_bytes, dimension, created_at,
+                        item.embedder_name, chunk.start_ms, chunk.end_ms
+                    ],
+                ).map_err(|e| VectorDbError::InsertFailed(e.to_string()))?;
+            }
+        }
+
+        // HNSW 快照的落盘放在同一个 `tx` 里，和上面的 document_chunks 行共享同一次提交：
+        // 避免“分块行已经落盘、HNSW 快照还没写”的崩溃窗口导致该分块永远无法被 ANN 搜到。
+        for (embedder_name, nodes, next_id, entry_point) in &touched_snapshots {
+            hnsw_persist_rows(&tx, embedder_name, nodes, *next_id, *entry_point)?;
+        }
+
+        tx.commit().map_err(|e| VectorDbError::InsertFailed(e.to_string()))?;
+        drop(conn);
+
+        // 事务成功提交后才把内存中的 HNSW 副本换回去，保证失败路径下 self.hnsw 不会与数据库不一致
+        let mut hnsw = self.hnsw.lock().unwrap();
+        for (embedder_name, index) in pending {
+            hnsw.insert(embedder_name, index);
+        }
+
         Ok(())
     }
 
-    /// 清除所有向量数据
-    pub fn clear_all(&self) -> Result<(), VectorDbError> {
+    /// 从嵌入缓存中读取向量，未命中返回 `None`
+    pub fn cache_get(&self, content_hash: &str, embedder_id: &str) -> Result<Option<Vec<f32>>, VectorDbError> {
         let conn = self.conn.lock().unwrap();
-        conn.execute("DELETE FROM document_vectors", [])?;
-        conn.execute("DELETE FROM documents", [])?;
+        let mut stmt = conn
+            .prepare("SELECT embedding, dimension FROM embedding_cache WHERE content_hash = ?1 AND embedder_id = ?2")
+            .map_err(|e| VectorDbError::SearchFailed(e.to_string()))?;
+
+        let mut rows = stmt
+            .query_map(params![content_hash, embedder_id], |row| {
+                let embedding_bytes: Vec<u8> = row.get(0)?;
+                let dimension: i32 = row.get(1)?;
+                Ok((embedding_bytes, dimension))
+            })
+            .map_err(|e| VectorDbError::SearchFailed(e.to_string()))?;
+
+        match rows.next() {
+            Some(row) => {
+                let (embedding_bytes, dimension) = row.map_err(|e| VectorDbError::SearchFailed(e.to_string()))?;
+                Ok(Some(bytes_to_f32_slice(&embedding_bytes, dimension as usize)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// 写入嵌入缓存
+    pub fn cache_put(&self, content_hash: &str, embedder_id: &str, embedding: &[f32]) -> Result<(), VectorDbError> {
+        let embedding_bytes = f32_slice_to_bytes(embedding);
+        let dimension = embedding.len() as i32;
+        let created_at = chrono::Utc::now().to_rfc3339();
+
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT OR REPLACE INTO embedding_cache (content_hash, embedder_id, embedding, dimension, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![content_hash, embedder_id, embedding_bytes, dimension, created_at],
+        ).map_err(|e| VectorDbError::InsertFailed(e.to_string()))?;
         Ok(())
     }
 
-    fn f32_slice_to_bytes(&self, slice: &[f32]) -> Vec<u8> {
-        let mut bytes = Vec::with_capacity(slice.len() * 4);
-        for &value in slice {
-            bytes.extend_from_slice(&value.to_le_bytes());
+    /// 清理不再被任何文档/分块引用的缓存条目，返回删除的行数
+    pub fn prune_cache(&self) -> Result<usize, VectorDbError> {
+        let conn = self.conn.lock().unwrap();
+
+        let mut retained: std::collections::HashSet<String> = std::collections::HashSet::new();
+
+        let mut chunk_stmt = conn
+            .prepare("SELECT chunk_text FROM document_chunks")
+            .map_err(|e| VectorDbError::SearchFailed(e.to_string()))?;
+        let chunk_rows = chunk_stmt
+            .query_map([], |row| row.get::<_, String>(0))
+            .map_err(|e| VectorDbError::SearchFailed(e.to_string()))?;
+        for row in chunk_rows {
+            retained.insert(content_hash(&row?));
+        }
+
+        let mut doc_stmt = conn
+            .prepare("SELECT content FROM documents")
+            .map_err(|e| VectorDbError::SearchFailed(e.to_string()))?;
+        let doc_rows = doc_stmt
+            .query_map([], |row| row.get::<_, String>(0))
+            .map_err(|e| VectorDbError::SearchFailed(e.to_string()))?;
+        for row in doc_rows {
+            retained.insert(content_hash(&row?));
+        }
+
+        let mut all_stmt = conn
+            .prepare("SELECT content_hash FROM embedding_cache")
+            .map_err(|e| VectorDbError::SearchFailed(e.to_string()))?;
+        let all_hashes: Vec<String> = all_stmt
+            .query_map([], |row| row.get::<_, String>(0))
+            .map_err(|e| VectorDbError::SearchFailed(e.to_string()))?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut removed = 0;
+        for hash in all_hashes {
+            if !retained.contains(&hash) {
+                removed += conn.execute(
+                    "DELETE FROM embedding_cache WHERE content_hash = ?1",
+                    params![hash],
+                )?;
+            }
         }
-        bytes
+        Ok(removed)
     }
 
-    fn bytes_to_f32_slice(&self, bytes: &[u8], dimension: usize) -> Vec<f32> {
-        let mut result = Vec::with_capacity(dimension);
-        for i in 0..dimension {
-            let start = i * 4;
-            let end = start + 4;
-            if end <= bytes.len() {
-                let bytes_array: [u8; 4] = bytes[start..end].try_into().unwrap();
-                result.push(f32::from_le_bytes(bytes_array));
+    /// 删除向量（含该文档的全部分块）
+    pub fn delete(&self, document_id: &str) -> Result<(), VectorDbError> {
+        let chunk_keys: Vec<(i64, String)> = {
+            let conn = self.conn.lock().unwrap();
+            let mut stmt = conn.prepare("SELECT chunk_index, embedder_name FROM document_chunks WHERE document_id = ?1")?;
+            let rows = stmt.query_map(params![document_id], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+            })?;
+            rows.collect::<Result<Vec<_>, _>>()?
+        };
+        let legacy_embedder: Option<String> = {
+            let conn = self.conn.lock().unwrap();
+            conn.query_row(
+                "SELECT embedder_name FROM document_vectors WHERE document_id = ?1",
+                params![document_id],
+                |row| row.get::<_, String>(0),
+            )
+            .ok()
+        };
+
+        {
+            let conn = self.conn.lock().unwrap();
+            conn.execute(
+                "DELETE FROM document_vectors WHERE document_id = ?1",
+                params![document_id],
+            )?;
+            conn.execute(
+                "DELETE FROM document_chunks WHERE document_id = ?1",
+                params![document_id],
+            )?;
+        }
+
+        let mut touched_embedders: Vec<String> = Vec::new();
+        {
+            let mut hnsw = self.hnsw.lock().unwrap();
+            if let Some(embedder_name) = &legacy_embedder {
+                if let Some(index) = hnsw.get_mut(embedder_name) {
+                    index.remove(document_id);
+                }
+                touched_embedders.push(embedder_name.clone());
             }
+            for (chunk_index, embedder_name) in &chunk_keys {
+                if let Some(index) = hnsw.get_mut(embedder_name) {
+                    index.remove(&hnsw_chunk_key(document_id, *chunk_index as usize));
+                }
+                if !touched_embedders.contains(embedder_name) {
+                    touched_embedders.push(embedder_name.clone());
+                }
+            }
+        }
+        for embedder_name in touched_embedders {
+            self.hnsw_persist(&embedder_name)?;
         }
-        result
+
+        Ok(())
     }
 
-    fn cosine_similarity(&self, a: &[f32], b: &[f32]) -> f32 {
-        if a.len() != b.len() {
-            return 0.0;
+    /// 清除所有向量数据
+    pub fn clear_all(&self) -> Result<(), VectorDbError> {
+        {
+            let conn = self.conn.lock().unwrap();
+            conn.execute("DELETE FROM document_vectors", [])?;
+            conn.execute("DELETE FROM document_chunks", [])?;
+            conn.execute("DELETE FROM documents", [])?;
+            conn.execute("DELETE FROM documents_fts", [])?;
+            conn.execute("DELETE FROM embedding_cache", [])?;
+            conn.execute("DELETE FROM hnsw_nodes", [])?;
+            conn.execute("DELETE FROM hnsw_edges", [])?;
+            conn.execute("DELETE FROM hnsw_meta", [])?;
         }
-        let dot_product: f32 = a.iter().zip(b.iter()).map(|(x, y)| x * y).sum();
-        let norm_a: f32 = a.iter().map(|x| x * x).sum::<f32>().sqrt();
-        let norm_b: f32 = b.iter().map(|x| x * x).sum::<f32>().sqrt();
-        if norm_a == 0.0 || norm_b == 0.0 {
-            return 0.0;
+        self.hnsw.lock().unwrap().clear();
+        Ok(())
+    }
+
+    /// 将某个嵌入器的 HNSW 索引整体重新落盘（先清空该嵌入器的行再全量写入），实现简单且不易出错，
+    /// 代价是持久化开销与该索引规模成正比，因此一次删除操作结束后对每个受影响的嵌入器只调用一次，而非每个分块单独调用
+    ///
+    /// 独立加锁、独立落盘，供不需要与其他 SQL 写入共享同一事务的调用方（如 `delete`/`delete_chunks`）使用。
+    /// 需要与文档行原子提交的场景（`commit_documents`）应改用 [`hnsw_persist_rows`]，在同一个事务里写入。
+    fn hnsw_persist(&self, embedder_name: &str) -> Result<(), VectorDbError> {
+        let (nodes, next_id, entry_point) = {
+            let hnsw = self.hnsw.lock().unwrap();
+            let Some(index) = hnsw.get(embedder_name) else { return Ok(()) };
+            (index.nodes_snapshot(), index.next_id(), index.entry_point())
+        };
+        let conn = self.conn.lock().unwrap();
+        hnsw_persist_rows(&conn, embedder_name, &nodes, next_id, entry_point)
+    }
+
+    /// 指定嵌入器的 HNSW 索引中当前的节点数，用于判断数据量是否已达到启用近似搜索的阈值
+    fn hnsw_len(&self, embedder_name: &str) -> usize {
+        self.hnsw.lock().unwrap().get(embedder_name).map(|index| index.len()).unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn doc_write<'a>(id: &'a str, content: &'a str, embedding: &'a [f32]) -> DocumentWrite<'a> {
+        DocumentWrite {
+            id,
+            name: id,
+            category: "test",
+            content,
+            source_path: None,
+            backup_path: None,
+            file_type: "txt",
+            created_at: "2026-01-01T00:00:00Z",
+            embedder_name: "test-embedder",
+            duration_ms: None,
+            chunks: vec![ChunkWrite {
+                index: 0,
+                text: content,
+                start_offset: 0,
+                end_offset: content.chars().count(),
+                start_ms: None,
+                end_ms: None,
+                embedding,
+            }],
         }
-        dot_product / (norm_a * norm_b)
+    }
+
+    #[test]
+    fn content_hash_is_deterministic_and_content_sensitive() {
+        assert_eq!(content_hash("hello"), content_hash("hello"));
+        assert_ne!(content_hash("hello"), content_hash("world"));
+    }
+
+    #[test]
+    fn cosine_similarity_errors_on_dimension_mismatch() {
+        let err = cosine_similarity(&[1.0, 0.0], &[1.0, 0.0, 0.0]).unwrap_err();
+        assert!(matches!(err, VectorDbError::DimensionMismatch { expected: 2, actual: 3 }));
+    }
+
+    #[test]
+    fn cosine_similarity_matches_expected_values() {
+        assert_eq!(cosine_similarity(&[1.0, 0.0], &[1.0, 0.0]).unwrap(), 1.0);
+        assert_eq!(cosine_similarity(&[1.0, 0.0], &[0.0, 1.0]).unwrap(), 0.0);
+    }
+
+    /// 混合搜索应当把语义与关键词两路都命中的文档排在最前，完全不命中任一路的文档排在最后，
+    /// 这是 RRF（Reciprocal Rank Fusion）融合排序最基本的健全性保证。
+    #[test]
+    fn search_hybrid_fuses_semantic_and_keyword_rankings() {
+        let db = VectorDb::new(Path::new(":memory:")).expect("in-memory sqlite should open");
+
+        let doc_a = doc_write("doc-a", "apple banana cherry", &[1.0, 0.0]);
+        let doc_b = doc_write("doc-b", "apple mango kiwi papaya", &[0.0, 1.0]);
+        let doc_c = doc_write("doc-c", "grape plum", &[-1.0, 0.0]);
+        db.commit_documents(&[doc_a, doc_b, doc_c]).expect("commit should succeed");
+
+        // 查询向量与 doc-a 的嵌入完全一致（语义最相关），查询词 "apple" 同时命中 doc-a 与 doc-b；
+        // doc-b 比 doc-a 更长，BM25 下关键词一路也是 doc-a 排第一，两路排名都领先
+        let results = db
+            .search_hybrid(&[1.0, 0.0], "apple", 10, 1.0, 1.0, "test-embedder")
+            .expect("hybrid search should succeed");
+        let ranking: Vec<&str> = results.iter().map(|(id, _)| id.as_str()).collect();
+
+        assert_eq!(ranking.first(), Some(&"doc-a"), "语义 + 关键词双命中的文档应当排第一");
+        assert_eq!(ranking.last(), Some(&"doc-c"), "两路都未命中的文档应当排最后");
+    }
+
+    /// 多词查询中的词在原文里既不相邻也不同序时，关键词一路仍应命中——这是区分
+    /// “整句短语匹配”和“各词 AND 匹配”的关键场景，只用单词查询测试无法发现退化成短语匹配的回归。
+    #[test]
+    fn search_hybrid_matches_multiword_query_with_non_contiguous_terms() {
+        let db = VectorDb::new(Path::new(":memory:")).expect("in-memory sqlite should open");
+
+        let doc_a = doc_write("doc-a", "banana apple cherry", &[1.0, 0.0]);
+        let doc_b = doc_write("doc-b", "grape plum", &[0.0, 1.0]);
+        db.commit_documents(&[doc_a, doc_b]).expect("commit should succeed");
+
+        // 查询词顺序与原文相反、且中间隔着 "cherry"，短语匹配会落空，AND 匹配应当命中 doc-a
+        let results = db
+            .search_hybrid(&[0.0, 1.0], "apple banana", 10, 1.0, 1.0, "test-embedder")
+            .expect("hybrid search should succeed");
+        let ranking: Vec<&str> = results.iter().map(|(id, _)| id.as_str()).collect();
+
+        assert_eq!(ranking.first(), Some(&"doc-a"), "非连续、逆序的多词查询也应当通过关键词一路命中");
     }
 }
@@ -1,3 +1,4 @@
+use crate::ai::embedder_registry::EmbedderInfo;
 use crate::ai::knowledge_base::{Document, KnowledgeBase, SearchResult};
 use crate::ai::service::{AiService, ChatMessage};
 // use crate::utils::paths::get_app_paths; // 需要实现 utils::paths
@@ -44,18 +45,71 @@ pub async fn init_knowledge_base(app: AppHandle, db_path: String) -> Result<(),
 }
 
 #[tauri::command]
-pub async fn add_document_to_kb(file_path: Option<String>, content: Option<String>, category: String) -> Result<Document, String> {
+pub async fn add_document_to_kb(
+    file_path: Option<String>, content: Option<String>, category: String, duration_ms: Option<u64>,
+) -> Result<Document, String> {
     let kb = KNOWLEDGE_BASE.lock().as_ref().cloned().ok_or("知识库未初始化")?;
-    
+
     let path_buf = file_path.map(PathBuf::from);
-    let doc = kb.add_document(path_buf.as_ref(), content, &category).await.map_err(|e| e.to_string())?;
+    let doc = kb.add_document(path_buf.as_ref(), content, &category, None, duration_ms).await.map_err(|e| e.to_string())?;
     Ok(doc)
 }
 
+/// 非阻塞地提交文档，分块与嵌入在后台队列中批量完成，调用方需要时可配合 `flush_knowledge_base` 等待入库完成
+#[tauri::command]
+pub async fn enqueue_document_to_kb(
+    file_path: Option<String>, content: Option<String>, category: String, duration_ms: Option<u64>,
+) -> Result<Document, String> {
+    let kb = KNOWLEDGE_BASE.lock().as_ref().cloned().ok_or("知识库未初始化")?;
+
+    let path_buf = file_path.map(PathBuf::from);
+    kb.enqueue_document(path_buf.as_ref(), content, &category, None, duration_ms).map_err(|e| e.to_string())
+}
+
+/// 等待后台嵌入队列中已提交的文档全部完成向量化与入库
+#[tauri::command]
+pub async fn flush_knowledge_base() -> Result<(), String> {
+    let kb = KNOWLEDGE_BASE.lock().as_ref().cloned().ok_or("知识库未初始化")?;
+    kb.flush().await;
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn search_knowledge_base(
+    query: String, limit: usize, semantic_ratio: Option<f32>, category: Option<String>,
+) -> Result<Vec<SearchResult>, String> {
+    let kb = KNOWLEDGE_BASE.lock().as_ref().cloned().ok_or("知识库未初始化")?;
+    kb.search_hybrid(&query, limit, semantic_ratio.unwrap_or(0.5), category.as_deref())
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// 注册（或覆盖同名）一个具名嵌入器：传入模型目录使用 ONNX 语义嵌入，否则回退到 SimpleEmbedder
 #[tauri::command]
-pub async fn search_knowledge_base(query: String, limit: usize) -> Result<Vec<SearchResult>, String> {
+pub async fn register_kb_embedder(name: String, model_dir: Option<String>) -> Result<(), String> {
     let kb = KNOWLEDGE_BASE.lock().as_ref().cloned().ok_or("知识库未初始化")?;
-    kb.search(&query, limit).await.map_err(|e| e.to_string())
+    kb.register_embedder(&name, model_dir.map(PathBuf::from).as_deref()).map_err(|e| e.to_string())
+}
+
+/// 将某个文档分类路由到指定（必须已注册）的嵌入器
+#[tauri::command]
+pub async fn route_kb_category(category: String, embedder_name: String) -> Result<(), String> {
+    let kb = KNOWLEDGE_BASE.lock().as_ref().cloned().ok_or("知识库未初始化")?;
+    kb.route_category(&category, &embedder_name).map_err(|e| e.to_string())
+}
+
+/// 列出全部已注册的嵌入器
+#[tauri::command]
+pub async fn list_kb_embedders() -> Result<Vec<EmbedderInfo>, String> {
+    let kb = KNOWLEDGE_BASE.lock().as_ref().cloned().ok_or("知识库未初始化")?;
+    Ok(kb.list_embedders())
+}
+
+/// 某个分类改换嵌入器后，重新对该分类下全部文档分块、向量化并入库，返回处理的文档数
+#[tauri::command]
+pub async fn reindex_kb_category(category: String) -> Result<usize, String> {
+    let kb = KNOWLEDGE_BASE.lock().as_ref().cloned().ok_or("知识库未初始化")?;
+    kb.reindex_category(&category).await.map_err(|e| e.to_string())
 }
 
 #[tauri::command]
@@ -107,3 +161,10 @@ pub async fn delete_document(id: String) -> Result<(), String> {
     let kb = KNOWLEDGE_BASE.lock().as_ref().cloned().ok_or("知识库未初始化")?;
     kb.delete_document(&id).await.map_err(|e| e.to_string())
 }
+
+/// 清理不再被任何文档引用的嵌入缓存条目，返回清理的条目数
+#[tauri::command]
+pub async fn prune_embedding_cache() -> Result<usize, String> {
+    let kb = KNOWLEDGE_BASE.lock().as_ref().cloned().ok_or("知识库未初始化")?;
+    kb.prune_cache().await.map_err(|e| e.to_string())
+}
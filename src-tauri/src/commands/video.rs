@@ -2,6 +2,7 @@
 // 支持视频上传、音频提取、ASR 语音识别
 
 use crate::core::sidecar_manager::ASR_GPU_PORT;
+use crate::utils::ffmpeg::FfmpegWrapper;
 use crate::utils::paths::get_temp_dir;
 use serde::{Deserialize, Serialize};
 use std::fs;
@@ -18,6 +19,8 @@ pub struct VideoInfo {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct TranscriptResult {
     pub text: String,
+    /// 视频总时长（毫秒），用于把转写文本的分块位置换算成大致的时间戳；探测失败时为 `None`
+    pub duration_ms: Option<u64>,
 }
 
 /// 上传视频（返回视频信息）
@@ -57,10 +60,26 @@ pub async fn transcribe_video(
     // 2. 调用 ASR GPU 服务进行转写
     let text = call_asr_service(&audio_path).await?;
 
-    // 3. 清理临时音频文件
+    // 3. 探测视频总时长，供调用方把分块字符偏移换算成大致的时间戳；ASR 服务不返回逐词时间戳，
+    //    探测失败（如 ffmpeg 未安装）不应阻断转写结果，退化为 None
+    let duration_ms = match FfmpegWrapper::new() {
+        Ok(ffmpeg) => match ffmpeg.get_duration_ms(Path::new(&video_path)) {
+            Ok(ms) => Some(ms),
+            Err(e) => {
+                tracing::warn!("探测视频时长失败: {}", e);
+                None
+            }
+        },
+        Err(e) => {
+            tracing::warn!("FFmpeg 不可用，无法探测视频时长: {}", e);
+            None
+        }
+    };
+
+    // 4. 清理临时音频文件
     let _ = fs::remove_file(&audio_path);
 
-    Ok(TranscriptResult { text })
+    Ok(TranscriptResult { text, duration_ms })
 }
 
 /// 查找 FFmpeg 路径
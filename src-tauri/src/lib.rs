@@ -51,12 +51,19 @@ pub fn run() {
             greet,
             commands::ai::init_knowledge_base,
             commands::ai::add_document_to_kb,
+            commands::ai::enqueue_document_to_kb,
+            commands::ai::flush_knowledge_base,
             commands::ai::search_knowledge_base,
             commands::ai::chat_with_ai,
             commands::ai::list_documents,
             commands::ai::delete_document,
+            commands::ai::prune_embedding_cache,
             commands::ai::get_document_content,
             commands::ai::open_document_file,
+            commands::ai::register_kb_embedder,
+            commands::ai::route_kb_category,
+            commands::ai::list_kb_embedders,
+            commands::ai::reindex_kb_category,
             commands::asr::check_asr_model,
             commands::asr::download_asr_model,
             commands::video::upload_video,
@@ -51,4 +51,26 @@ impl FfmpegWrapper {
 
         Ok(())
     }
+
+    /// Returns the total duration of a media file in milliseconds.
+    ///
+    /// `ffmpeg -i` with no output file always exits non-zero, but it still prints the
+    /// container's `Duration: HH:MM:SS.cc` line to stderr, which is all we need here.
+    pub fn get_duration_ms(&self, media_path: &Path) -> Result<u64, FfmpegError> {
+        let output = Command::new("ffmpeg").arg("-i").arg(media_path).output()?;
+        let stderr = String::from_utf8_lossy(&output.stderr);
+
+        let re = regex::Regex::new(r"Duration:\s*(\d+):(\d+):(\d+)\.(\d+)")
+            .expect("hardcoded duration regex is valid");
+        let caps = re
+            .captures(&stderr)
+            .ok_or_else(|| FfmpegError::ExecutionFailed("could not find Duration in ffmpeg output".to_string()))?;
+
+        let hours: u64 = caps[1].parse().unwrap_or(0);
+        let minutes: u64 = caps[2].parse().unwrap_or(0);
+        let seconds: u64 = caps[3].parse().unwrap_or(0);
+        let centis: u64 = caps[4].parse().unwrap_or(0);
+
+        Ok(((hours * 3600 + minutes * 60 + seconds) * 1000) + centis * 10)
+    }
 }